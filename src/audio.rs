@@ -1,12 +1,15 @@
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use dasp::sample::FromSample;
+use eframe::egui;
 use hound::{WavSpec, WavWriter};
 use rodio::Sample;
 use rodio::Source;
 use rodio::{OutputStream, Sink};
+use std::fmt;
+use std::io::{Seek, SeekFrom, Write};
 use std::path::Path;
 use tokio::fs::File;
-use tokio::io::{self, AsyncWriteExt};
+use tokio::io::AsyncWriteExt;
 
 use std::sync::mpsc::Sender;
 use std::sync::{
@@ -14,7 +17,7 @@ use std::sync::{
     Arc, Mutex,
 };
 
-use super::freq;
+use crate::weighting::Weighting;
 
 pub fn get_input_devices() -> Result<cpal::InputDevices<cpal::Devices>, cpal::DevicesError> {
     let host = cpal::default_host();
@@ -52,18 +55,160 @@ fn select_output_device(device_name: String) -> cpal::Device {
     }
 }
 
-pub fn capture_input(
+/// Capture is audio recorded from an input device, de-interleaved into one buffer
+/// per channel.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Capture {
+    /// Sample rate the device streamed at.
+    pub sample_rate: f32,
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl Capture {
+    pub fn new(sample_rate: f32, channel_count: usize) -> Self {
+        Self {
+            sample_rate,
+            channels: vec![Vec::new(); channel_count],
+        }
+    }
+
+    /// Appends `data`, whose samples are interleaved across the channels. A frame
+    /// cut short at the end of `data` is dropped.
+    pub fn push_interleaved(&mut self, data: &[f32]) {
+        let count = self.channels.len();
+        if count == 0 {
+            return;
+        }
+        for frame in data.chunks_exact(count) {
+            for (channel, sample) in self.channels.iter_mut().zip(frame) {
+                channel.push(*sample);
+            }
+        }
+    }
+
+    /// Returns the number of samples per channel.
+    pub fn len(&self) -> usize {
+        self.channels.first().map_or(0, Vec::len)
+    }
+
+    /// Returns the captured channels in `selection`, with their index.
+    pub fn selected(&self, selection: Channels) -> Vec<(usize, &[f32])> {
+        self.channels
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| selection.contains(*i))
+            .map(|(i, channel)| (i, channel.as_slice()))
+            .collect()
+    }
+
+    /// Returns the mean of the channels in `selection`, or the first channel if none
+    /// of them was captured, as when the device has fewer channels than selected.
+    pub fn mix(&self, selection: Channels) -> Vec<f32> {
+        let selected: Vec<&[f32]> = self
+            .selected(selection)
+            .into_iter()
+            .map(|(_, channel)| channel)
+            .collect();
+        match selected.as_slice() {
+            [] => self.channels.first().cloned().unwrap_or_default(),
+            [channel] => channel.to_vec(),
+            _ => (0..self.len())
+                .map(|i| selected.iter().map(|c| c[i]).sum::<f32>() / selected.len() as f32)
+                .collect(),
+        }
+    }
+}
+
+/// Channels is a set of input channels, numbered from 0, that are averaged into the
+/// signal analyzed or exported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channels(u64);
+
+impl Default for Channels {
+    /// The first channel.
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+impl fmt::Display for Channels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let numbers: Vec<String> = (0..64)
+            .filter(|&i| self.contains(i))
+            .map(|i| (i + 1).to_string())
+            .collect();
+        if numbers.is_empty() {
+            return write!(f, "none");
+        }
+        write!(f, "{}", numbers.join(" + "))
+    }
+}
+
+impl Channels {
+    /// Every channel, as many as are captured.
+    pub fn all() -> Self {
+        Self(u64::MAX)
+    }
+
+    pub fn contains(&self, channel: usize) -> bool {
+        channel < 64 && self.0 & (1 << channel) != 0
+    }
+
+    /// Paints a checkbox for each of the first `count` channels after `label`. The
+    /// last checked one cannot be unchecked, so that the selection is never empty.
+    pub fn paint_settings(&mut self, ui: &mut egui::Ui, label: &str, count: usize, disabled: bool) {
+        ui.horizontal(|ui| {
+            if disabled {
+                ui.disable();
+            }
+            ui.label(label);
+            let count = count.clamp(1, 64);
+            let checked = (0..count).filter(|&channel| self.contains(channel)).count();
+            for channel in 0..count {
+                let mut selected = self.contains(channel);
+                if ui
+                    .add_enabled(
+                        !selected || checked > 1,
+                        egui::Checkbox::new(&mut selected, (channel + 1).to_string()),
+                    )
+                    .on_disabled_hover_text("At least one channel is selected")
+                    .changed()
+                {
+                    self.0 ^= 1 << channel;
+                }
+            }
+        });
+    }
+}
+
+/// Captures the input device into `buffer` while `is_playing` is set, then sends the
+/// result of `analyze` on the whole capture through `for_tx`.
+///
+/// The capture is taken at the default input configuration of the device, whose
+/// sample rate is not necessarily the one the analysis expects. A capture left in
+/// `buffer` with the same configuration is appended to, any other is replaced.
+pub fn capture_input<T, F>(
     input_device_name: String,
-    sample_rate: f32,
-    buffer: Arc<Mutex<Vec<f32>>>,
-    for_tx: Sender<f32>,
+    buffer: Arc<Mutex<Capture>>,
+    for_tx: Sender<T>,
     is_playing: Arc<AtomicBool>,
-) {
+    analyze: F,
+) where
+    F: FnOnce(&Capture) -> T,
+{
     if !is_playing.load(Ordering::SeqCst) {
         return;
     }
     let input_device = select_input_device(input_device_name);
     let config_range = input_device.default_input_config().unwrap();
+    let sample_rate = config_range.sample_rate().0 as f32;
+    let channel_count = config_range.channels() as usize;
+    {
+        let mut capture = buffer.lock().unwrap();
+        if capture.sample_rate != sample_rate || capture.channels.len() != channel_count {
+            *capture = Capture::new(sample_rate, channel_count);
+        }
+    }
 
     let data_clone = Arc::clone(&buffer);
 
@@ -72,7 +217,7 @@ pub fn capture_input(
             &config_range.into(),
             move |data: &[f32], _: &cpal::InputCallbackInfo| {
                 let mut locked_data = data_clone.lock().unwrap();
-                locked_data.extend_from_slice(data);
+                locked_data.push_interleaved(data);
             },
             move |err| {
                 eprintln!("An error occurred on the input stream: {}", err);
@@ -86,8 +231,7 @@ pub fn capture_input(
     }
     input_stream.pause().unwrap();
     let locked_data = buffer.lock().unwrap();
-    let ffr = freq::freq_of_resonance(locked_data.clone(), sample_rate);
-    for_tx.send(ffr).unwrap();
+    for_tx.send(analyze(&locked_data)).unwrap();
 }
 
 pub fn play_output<S>(output_device_name: String, sound: S, stop_signal: Arc<AtomicBool>)
@@ -107,7 +251,7 @@ where
 }
 
 pub fn save_mono_vec_to_wav(
-    data: &Vec<f32>,
+    data: &[f32],
     sample_rate: u32,
     file_path: &Path,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -131,21 +275,200 @@ pub fn save_mono_vec_to_wav(
     Ok(())
 }
 
+/// Saves the channels of `capture` in `selection` as the tracks of a float WAV file,
+/// with `comment` in its INFO list.
+pub fn save_capture_to_wav(
+    capture: &Capture,
+    selection: Channels,
+    comment: &str,
+    file_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let selected = capture.selected(selection);
+    let spec = WavSpec {
+        channels: selected.len().max(1) as u16,
+        sample_rate: capture.sample_rate as u32,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = WavWriter::create(file_path, spec)?;
+    for i in 0..capture.len() {
+        for (_, channel) in &selected {
+            writer.write_sample(channel[i])?;
+        }
+    }
+    writer.finalize()?;
+    append_wav_comment(file_path, comment)?;
+    Ok(())
+}
+
+/// Appends `comment` to the WAV file at `file_path` as the ICMT entry of a LIST INFO
+/// chunk, and updates the RIFF size to include it.
+fn append_wav_comment(file_path: &Path, comment: &str) -> std::io::Result<()> {
+    let mut text = comment.as_bytes().to_vec();
+    text.push(0);
+    let size = text.len() as u32;
+    if text.len() % 2 == 1 {
+        text.push(0);
+    }
+    let mut chunk = b"LIST".to_vec();
+    chunk.extend((12 + text.len() as u32).to_le_bytes());
+    chunk.extend(b"INFOICMT");
+    chunk.extend(size.to_le_bytes());
+    chunk.extend(text);
+
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(file_path)?;
+    let len = file.seek(SeekFrom::End(0))?;
+    file.write_all(&chunk)?;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&((len + chunk.len() as u64 - 8) as u32).to_le_bytes())
+}
+
+/// Saves `rows` under `header` as CSV, after `metadata` as lines starting with `#`.
+/// NaN values are left as empty cells.
+pub async fn save_columns_to_csv(
+    metadata: &[String],
+    header: &[String],
+    rows: &[Vec<f32>],
+    file_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = File::create(file_path).await?;
+    for line in metadata {
+        file.write_all(format!("# {}\n", line).as_bytes()).await?;
+    }
+    file.write_all(format!("{}\n", header.join(",")).as_bytes())
+        .await?;
+    for row in rows {
+        let cells: Vec<String> = row
+            .iter()
+            .map(|v| {
+                if v.is_nan() {
+                    String::new()
+                } else {
+                    v.to_string()
+                }
+            })
+            .collect();
+        file.write_all(format!("{}\n", cells.join(",")).as_bytes())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Saves the channels of `capture` in `selection` as CSV after `metadata`, each sample
+/// followed by its weighted amplitude in dB.
+pub async fn save_capture_with_db_to_csv(
+    capture: &Capture,
+    selection: Channels,
+    weighting: Weighting,
+    metadata: &[String],
+    file_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let selected = capture.selected(selection);
+    let mut header = vec!["Time (s)".to_string()];
+    for (i, _) in &selected {
+        header.push(format!("Channel {} sample value", i + 1));
+        header.push(format!(
+            "Channel {} amplitude ({})",
+            i + 1,
+            weighting.unit()
+        ));
+    }
+    let weighted: Vec<Vec<f32>> = selected
+        .iter()
+        .map(|(_, channel)| weighting.apply(channel, capture.sample_rate))
+        .collect();
+    let rows: Vec<Vec<f32>> = (0..capture.len())
+        .map(|i| {
+            let mut row = vec![i as f32 / capture.sample_rate];
+            for ((_, channel), weighted) in selected.iter().zip(&weighted) {
+                row.push(channel[i]);
+                row.push(20.0 * weighted[i].abs().log10());
+            }
+            row
+        })
+        .collect();
+    save_columns_to_csv(metadata, &header, &rows, file_path).await
+}
+
 pub async fn save_mono_vec_with_db_to_csv(
-    data: &Vec<f32>,
+    data: &[f32],
     sample_rate: u32,
+    weighting: Weighting,
     file_path: &Path,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut file = File::create(file_path).await?;
-    file.write(b"Time (s),Sample Value,Amplitude (dB) \n")
+    file.write_all(format!("Time (s),Sample Value,Amplitude ({}) \n", weighting.unit()).as_bytes())
         .await?; // Add header
 
-    for (i, sample) in data.iter().enumerate() {
+    let weighted = weighting.apply(data, sample_rate as f32);
+    for (i, (sample, weighted)) in data.iter().zip(&weighted).enumerate() {
         let time = i as f32 / sample_rate as f32;
-        let db_value = 20.0 * sample.abs().log10(); // Calculate amplitude in dB
-        file.write(format!("{},{},{} \n", time, sample, db_value).as_bytes())
+        let db_value = 20.0 * weighted.abs().log10(); // Calculate amplitude in dB
+        file.write_all(format!("{},{},{} \n", time, sample, db_value).as_bytes())
             .await?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deinterleave_and_mix_channels() {
+        let mut capture = Capture::new(48000.0, 3);
+        // Two callbacks, the second one ending with half a frame.
+        capture.push_interleaved(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        capture.push_interleaved(&[7.0, 8.0, 9.0, 10.0]);
+        assert_eq!(capture.len(), 3);
+        assert_eq!(
+            capture.channels,
+            vec![
+                vec![1.0, 4.0, 7.0],
+                vec![2.0, 5.0, 8.0],
+                vec![3.0, 6.0, 9.0]
+            ]
+        );
+
+        assert_eq!(capture.mix(Channels::default()), vec![1.0, 4.0, 7.0]);
+        let mut selection = Channels::default();
+        selection.0 |= 1 << 2;
+        assert_eq!(selection.to_string(), "1 + 3");
+        assert_eq!(capture.mix(selection), vec![2.0, 5.0, 8.0]);
+        // Channels that were not captured are left out.
+        assert_eq!(capture.mix(Channels(1 << 5)), vec![1.0, 4.0, 7.0]);
+    }
+
+    #[test]
+    fn test_save_capture_to_wav_with_comment() {
+        let mut capture = Capture::new(48000.0, 2);
+        capture.push_interleaved(&[0.1, -0.1, 0.2, -0.2, 0.3, -0.3]);
+        let path = std::env::temp_dir().join("caliber_test_capture.wav");
+        let comment = "Preprocessing: DC removal";
+        save_capture_to_wav(&capture, Channels::all(), comment, &path).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let riff_size = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        assert_eq!(riff_size as usize, bytes.len() - 8);
+        assert!(bytes
+            .windows(comment.len())
+            .any(|window| window == comment.as_bytes()));
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, vec![0.1, -0.1, 0.2, -0.2, 0.3, -0.3]);
+
+        // The second channel alone.
+        save_capture_to_wav(&capture, Channels(1 << 1), comment, &path).unwrap();
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        assert_eq!(reader.spec().channels, 1);
+        let samples: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, vec![-0.1, -0.2, -0.3]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
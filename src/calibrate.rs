@@ -1,12 +1,29 @@
 // GUI
 use eframe::egui;
-use egui_plot::{Line, Plot, PlotPoints};
+use egui_plot::{Bar, BarChart, HLine, Legend, Line, Plot, PlotPoints};
 
 // Audio
 use cpal::traits::DeviceTrait;
 
-use crate::audio;
+use crate::audio::{self, Capture, Channels};
 use crate::chirp::Chirp;
+use crate::freq::{
+    self, Band, Estimator, Interpolation, NoResonance, Psd, Resonance, WelchSettings, Zoom,
+};
+use crate::frf::{self, Frf, FrfEstimator, FrfSettings};
+use crate::hilbert::{self, Instantaneous};
+use crate::latency::{self, Latency};
+use crate::modal::{self, ModalFit, ModalSettings};
+use crate::octave::{self, Fraction};
+use crate::parametric::{self, Component, Method, ParametricSettings};
+use crate::peaks::{self, Peak, PeakSettings};
+use crate::preprocess::Preprocess;
+use crate::resample;
+use crate::ringdown::{self, Ringdown, RingdownSettings};
+use crate::spectrogram::SpectrogramView;
+use crate::sweep::{self, SweepResponse, SweepSettings};
+use crate::weighting::Weighting;
+use crate::window::Window;
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{
@@ -21,8 +38,77 @@ use crate::utils::Result;
 
 // Constants
 const DEFAULT_SAMPLE_RATE: f32 = 192000.0;
-const DEFAULT_CAPTURED_INPUT_SAMPLE_RATE: f32 = 44100.0;
 const DEFAULT_DOWNSAMPLE_FACTOR: f32 = 1000.0;
+/// Storage key of the preprocessing chain.
+const PREPROCESS_KEY: &str = "calibrate_preprocess";
+
+/// CalibrationResult is the analysis of one capture.
+#[derive(Debug, Clone)]
+struct CalibrationResult {
+    resonance: std::result::Result<Resonance, NoResonance>,
+    peaks: Vec<Peak>,
+    frf: Frf,
+    psd: Psd,
+    /// Finer PSD of the whole capture, the fractional-octave bands are integrated from.
+    octave_psd: Psd,
+    sweep: Option<SweepResponse>,
+    ringdown: Option<Ringdown>,
+    /// Band the resonance was searched in.
+    band: Band,
+    /// Preprocessing applied to the capture before the analysis.
+    preprocess: Preprocess,
+    /// Delay of the capture behind the excitation, removed before the FRF and the
+    /// sweep analysis.
+    latency: Option<Latency>,
+    /// The preprocessed capture.
+    capture: Vec<f32>,
+    /// Envelope and instantaneous frequency of the preprocessed capture.
+    instantaneous: Instantaneous,
+    /// Damped sinusoids fitted to the ring-down, when the resonance is estimated
+    /// parametrically.
+    components: Vec<Component>,
+    /// Sample rate the input device streamed at, None before the first capture. The
+    /// capture is resampled to `DEFAULT_SAMPLE_RATE` when it differs.
+    stream_sample_rate: Option<f32>,
+    /// Channels averaged into the analyzed signal.
+    channels: Channels,
+}
+
+impl Default for CalibrationResult {
+    /// Nothing captured yet.
+    fn default() -> Self {
+        Self {
+            resonance: Err(NoResonance::TooFewSamples),
+            peaks: Vec::new(),
+            frf: Frf::default(),
+            psd: Psd::default(),
+            octave_psd: Psd::default(),
+            sweep: None,
+            ringdown: None,
+            band: Band::default(),
+            preprocess: Preprocess::default(),
+            latency: None,
+            capture: Vec::new(),
+            instantaneous: Instantaneous::default(),
+            components: Vec::new(),
+            stream_sample_rate: None,
+            channels: Channels::default(),
+        }
+    }
+}
+
+/// PeakColumn is a column of the peaks table that it can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PeakColumn {
+    Frequency,
+    Magnitude,
+    Prominence,
+    Bandwidth,
+    Q,
+    DampingRatio,
+    Snr,
+    Coherence,
+}
 
 pub struct CalibrateTab {
     current_chirp: Option<Chirp>,
@@ -30,15 +116,51 @@ pub struct CalibrateTab {
     chirp_start: Option<f32>,
     chirp_end: Option<f32>,
     output_sample_rate: Option<f32>,
-    captured_input_sample_rate: f32,
     is_playing: Arc<AtomicBool>,
     started_sound: bool,
     start_time: Instant,
     points_vector: Vec<[f64; 2]>,
-    for_tx: Sender<f32>,
-    for_rx: Receiver<f32>,
-    captured_buffer: Arc<Mutex<Vec<f32>>>,
-    last_for: f32,
+    for_tx: Sender<CalibrationResult>,
+    for_rx: Receiver<CalibrationResult>,
+    captured_buffer: Arc<Mutex<Capture>>,
+    channels: Channels,
+    /// Channels written by the capture exports.
+    export_channels: Channels,
+    last_result: CalibrationResult,
+    peak_settings: PeakSettings,
+    peak_sort: PeakColumn,
+    peak_sort_descending: bool,
+    frf_settings: FrfSettings,
+    show_raw_capture: bool,
+    welch_settings: WelchSettings,
+    use_welch: bool,
+    /// Estimate the resonance from the ring-down with `parametric_settings` rather
+    /// than from the spectrum.
+    use_parametric: bool,
+    parametric_settings: ParametricSettings,
+    sweep_settings: SweepSettings,
+    max_harmonic: usize,
+    spectrogram: SpectrogramView,
+    ringdown_settings: RingdownSettings,
+    /// Seconds captured after the chirp ends, for the ring-down analysis.
+    ringdown_tail: f32,
+    /// Search the resonance in `search_band` rather than in the chirp band.
+    custom_band: bool,
+    search_band: Band,
+    /// Half-width in Hz of the band zoomed into around the resonance.
+    refine_span: f32,
+    refine_points: usize,
+    zoom: Option<Zoom>,
+    preprocess: Preprocess,
+    octave_fraction: Fraction,
+    weighting: Weighting,
+    /// Weighted level of the last capture, with the weighting it was computed for.
+    weighted_level: Option<(Weighting, f32)>,
+    modal_settings: ModalSettings,
+    modal_fit: Option<ModalFit>,
+    /// Number of frequency bins of the envelope response curve.
+    response_curve_bins: usize,
+    estimator: Estimator,
     input_device_name: String,
     output_device_name: String,
     drain_graphs: bool,
@@ -48,16 +170,16 @@ pub struct CalibrateTab {
 
 impl CalibrateTab {
     pub fn new(
-        _cc: &eframe::CreationContext<'_>,
+        cc: &eframe::CreationContext<'_>,
         status_tx: tokio::sync::mpsc::Sender<String>,
     ) -> Self {
-        let captured_input_sample_rate = DEFAULT_CAPTURED_INPUT_SAMPLE_RATE;
         let start_time = Instant::now();
         let is_playing = Arc::new(AtomicBool::new(false));
         let started_sound = false;
         let points_vector = vec![];
-        let (for_tx, for_rx): (Sender<f32>, Receiver<f32>) = mpsc::channel();
-        let captured_buffer = Arc::new(Mutex::new(Vec::<f32>::new()));
+        let (for_tx, for_rx): (Sender<CalibrationResult>, Receiver<CalibrationResult>) =
+            mpsc::channel();
+        let captured_buffer = Arc::new(Mutex::new(Capture::default()));
         let drain_graphs = true;
         Self {
             chirp_start: None,
@@ -65,7 +187,6 @@ impl CalibrateTab {
             output_sample_rate: None,
             current_chirp: None,
             duration: None,
-            captured_input_sample_rate,
             is_playing,
             started_sound,
             start_time,
@@ -73,7 +194,42 @@ impl CalibrateTab {
             for_tx,
             for_rx,
             captured_buffer,
-            last_for: 0.0,
+            channels: Channels::default(),
+            export_channels: Channels::all(),
+            last_result: CalibrationResult::default(),
+            peak_settings: PeakSettings::default(),
+            peak_sort: PeakColumn::Magnitude,
+            peak_sort_descending: true,
+            frf_settings: FrfSettings::default(),
+            show_raw_capture: false,
+            welch_settings: WelchSettings::default(),
+            use_welch: false,
+            use_parametric: false,
+            parametric_settings: ParametricSettings::default(),
+            sweep_settings: SweepSettings::default(),
+            max_harmonic: 5,
+            spectrogram: SpectrogramView::new("calibrate_spectrogram"),
+            ringdown_settings: RingdownSettings::default(),
+            ringdown_tail: 1.0,
+            custom_band: false,
+            search_band: Band {
+                min: 20.0,
+                max: 20000.0,
+            },
+            refine_span: 50.0,
+            refine_points: 2000,
+            zoom: None,
+            preprocess: cc
+                .storage
+                .and_then(|storage| eframe::get_value(storage, PREPROCESS_KEY))
+                .unwrap_or_default(),
+            octave_fraction: Fraction::default(),
+            weighting: Weighting::default(),
+            weighted_level: None,
+            modal_settings: ModalSettings::default(),
+            modal_fit: None,
+            response_curve_bins: 500,
+            estimator: Estimator::default(),
             input_device_name: "Default".to_string(),
             output_device_name: "Default".to_string(),
             drain_graphs,
@@ -82,6 +238,11 @@ impl CalibrateTab {
         }
     }
 
+    /// Saves the settings restored by `new`.
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, PREPROCESS_KEY, &self.preprocess);
+    }
+
     fn plot(&mut self) {
         self.is_playing.store(true, Ordering::SeqCst);
         self.start_time = Instant::now();
@@ -115,19 +276,142 @@ impl CalibrateTab {
         // Start the wave playing thread.
         let is_playing = self.is_playing.clone();
         let sound = self.current_chirp.clone().ok_or("no chirp found")?;
+        // The capture is analyzed at DEFAULT_SAMPLE_RATE, and so is the excitation.
+        let excitation = resample::resample(&sound.samples, sound.sample_rate, DEFAULT_SAMPLE_RATE);
+        let sweep_settings = sound.sweep;
         spawn(move || {
             audio::play_output(output_device_name, sound, is_playing);
         });
 
         // Start the wave capturing thread.
         let is_playing = self.is_playing.clone();
+        let estimator = Estimator {
+            band: self.resonance_band(),
+            ..self.estimator
+        };
+        let peak_settings = self.peak_settings;
+        let frf_settings = FrfSettings {
+            window: estimator.window,
+            ..self.frf_settings
+        };
+        let welch_settings = WelchSettings {
+            window: estimator.window,
+            ..self.welch_settings
+        };
+        let use_welch = self.use_welch;
+        let parametric_settings = self.use_parametric.then_some(self.parametric_settings);
+        let max_harmonic = self.max_harmonic;
+        let ringdown_settings = self.ringdown_settings;
+        let preprocess = self.preprocess;
+        let channels = self.channels;
         spawn(move || {
             audio::capture_input(
                 input_device_name,
-                DEFAULT_SAMPLE_RATE,
                 captured_buffer,
                 for_tx,
                 is_playing,
+                |raw| {
+                    let samples = resample::resample(
+                        &raw.mix(channels),
+                        raw.sample_rate,
+                        DEFAULT_SAMPLE_RATE,
+                    );
+                    let capture = preprocess.apply(&samples, DEFAULT_SAMPLE_RATE);
+                    let samples = capture.as_slice();
+                    let psd = freq::welch(samples, DEFAULT_SAMPLE_RATE, &welch_settings);
+                    let (resonance, peaks) = if use_welch {
+                        (
+                            freq::freq_of_resonance_in_psd(
+                                &psd,
+                                estimator.interpolation,
+                                estimator.band,
+                                &estimator.detection,
+                            ),
+                            peaks::find_peaks_in_psd(
+                                samples,
+                                DEFAULT_SAMPLE_RATE,
+                                &psd,
+                                estimator.interpolation,
+                                estimator.band,
+                                &estimator.detection,
+                                &peak_settings,
+                            ),
+                        )
+                    } else {
+                        (
+                            freq::freq_of_resonance(samples, DEFAULT_SAMPLE_RATE, &estimator),
+                            peaks::find_peaks(
+                                samples,
+                                DEFAULT_SAMPLE_RATE,
+                                &estimator,
+                                &peak_settings,
+                            ),
+                        )
+                    };
+                    let delay = latency::estimate(&excitation, samples, DEFAULT_SAMPLE_RATE);
+                    let components = parametric_settings.map(|settings| {
+                        // The ring-down starts where the excitation ends in the capture.
+                        let start = excitation.len()
+                            + delay.map_or(0, |delay| delay.samples.max(0.0).ceil() as usize);
+                        parametric::estimate(samples, start, DEFAULT_SAMPLE_RATE, &settings)
+                    });
+                    let resonance = match &components {
+                        Some(Ok(components)) => {
+                            parametric::resonance(components, estimator.band, &estimator.detection)
+                        }
+                        Some(Err(reason)) => Err(*reason),
+                        None => resonance,
+                    };
+                    let aligned = match &delay {
+                        Some(delay) => latency::align(samples, delay),
+                        None => samples.to_vec(),
+                    };
+                    let frf = frf::estimate_frf(
+                        &excitation,
+                        &aligned,
+                        DEFAULT_SAMPLE_RATE,
+                        &frf_settings,
+                    );
+                    CalibrationResult {
+                        resonance,
+                        peaks: peaks::check_coherence(peaks, &frf, &peak_settings),
+                        frf,
+                        psd,
+                        octave_psd: freq::welch(
+                            samples,
+                            DEFAULT_SAMPLE_RATE,
+                            &octave::welch_settings(
+                                estimator.band,
+                                DEFAULT_SAMPLE_RATE,
+                                estimator.window,
+                            ),
+                        ),
+                        ringdown: resonance.ok().and_then(|resonance| {
+                            ringdown::analyze(
+                                samples,
+                                DEFAULT_SAMPLE_RATE,
+                                resonance.frequency,
+                                &ringdown_settings,
+                            )
+                        }),
+                        band: estimator.band,
+                        sweep: sweep_settings.map(|settings| {
+                            sweep::deconvolve(
+                                &aligned,
+                                &settings,
+                                DEFAULT_SAMPLE_RATE,
+                                max_harmonic,
+                            )
+                        }),
+                        preprocess,
+                        latency: delay,
+                        instantaneous: hilbert::instantaneous(samples, DEFAULT_SAMPLE_RATE),
+                        components: components.and_then(|c| c.ok()).unwrap_or_default(),
+                        stream_sample_rate: Some(raw.sample_rate),
+                        channels,
+                        capture,
+                    }
+                },
             )
         });
         Ok(())
@@ -147,10 +431,10 @@ impl CalibrateTab {
             if ui.button("Clear").clicked() {
                 self.points_vector.clear();
                 if let Ok(mut buffer) = self.captured_buffer.lock() {
-                    buffer.clear();
+                    *buffer = Capture::default();
                     return;
                 }
-                self.captured_buffer = Arc::new(Mutex::new(Vec::new()));
+                self.captured_buffer = Arc::new(Mutex::new(Capture::default()));
             }
         });
         Ok(())
@@ -242,16 +526,13 @@ impl CalibrateTab {
                     }
                 };
                 let path = file.to_path_buf();
-                ui.label(format!(
-                    "{}",
-                    match path.to_str() {
-                        Some(v) => v,
-                        None => {
-                            self.send_error("corrupted file path".to_string());
-                            return;
-                        }
+                ui.label(match path.to_str() {
+                    Some(v) => v,
+                    None => {
+                        self.send_error("corrupted file path".to_string());
+                        return;
                     }
-                ));
+                });
                 let wav_data = match hound::WavReader::open(path) {
                     Ok(v) => v,
                     Err(e) => {
@@ -266,15 +547,56 @@ impl CalibrateTab {
                         return;
                     }
                 };
-                self.duration = Some(chirp.duration.clone());
-                self.output_sample_rate = Some(chirp.sample_rate.clone());
-                self.chirp_start = Some(chirp.start_freq.clone());
-                self.chirp_end = Some(chirp.end_freq.clone());
+                self.duration = Some(chirp.duration);
+                self.output_sample_rate = Some(chirp.sample_rate);
+                self.chirp_start = Some(chirp.start_freq);
+                self.chirp_end = Some(chirp.end_freq);
                 self.current_chirp = Some(chirp);
             };
         });
     }
 
+    fn paint_sweep_generator_input(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if self.is_playing.load(Ordering::SeqCst) {
+                ui.disable();
+            }
+            ui.label("Exponential sweep: ");
+            ui.add(
+                egui::DragValue::new(&mut self.sweep_settings.start_freq)
+                    .range(1.0..=DEFAULT_SAMPLE_RATE / 2.0)
+                    .suffix(" Hz"),
+            );
+            ui.label("to");
+            ui.add(
+                egui::DragValue::new(&mut self.sweep_settings.end_freq)
+                    .range(1.0..=DEFAULT_SAMPLE_RATE / 2.0)
+                    .suffix(" Hz"),
+            );
+            ui.label("over");
+            ui.add(
+                egui::DragValue::new(&mut self.sweep_settings.duration)
+                    .speed(0.1)
+                    .range(0.1..=60.0)
+                    .suffix(" s"),
+            );
+            ui.label("Harmonics:");
+            ui.add(egui::DragValue::new(&mut self.max_harmonic).range(1..=10));
+            if ui.button("Generate").clicked() {
+                if self.sweep_settings.start_freq >= self.sweep_settings.end_freq {
+                    self.send_error("sweep start must be below its end frequency".to_string());
+                    return;
+                }
+                let chirp = Chirp::exponential(self.sweep_settings, DEFAULT_SAMPLE_RATE);
+                self.duration = Some(chirp.duration);
+                self.output_sample_rate = Some(chirp.sample_rate);
+                self.chirp_start = Some(chirp.start_freq);
+                self.chirp_end = Some(chirp.end_freq);
+                self.current_chirp = Some(chirp);
+            }
+        });
+    }
+
     fn paint_output_sample_rate_input(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             ui.label("Chrip sample rate: ");
@@ -287,27 +609,803 @@ impl CalibrateTab {
         });
     }
 
-    fn paint_captured_input_sample_rate(&mut self, ui: &mut egui::Ui) {
+    /// Paints the rate the input device streams at, known once it has started.
+    fn paint_stream_sample_rate(&self, ui: &mut egui::Ui) {
+        let sample_rate = self
+            .captured_buffer
+            .lock()
+            .map_or(0.0, |capture| capture.sample_rate);
+        ui.horizontal(|ui| {
+            ui.label("Input stream sample rate: ");
+            if sample_rate > 0.0 {
+                ui.label(format!("{} Hz", sample_rate));
+            } else {
+                ui.label("not captured yet");
+            }
+        });
+    }
+
+    fn paint_window_input(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
-            ui.label("Captured input sample rate: ");
             if self.is_playing.load(Ordering::SeqCst) {
                 ui.disable();
             }
-            let mut val = format!("{}", self.captured_input_sample_rate).to_string();
-            ui.add(egui::TextEdit::singleline(&mut val));
-            ui.label("Hz");
-            if val == "" {
-                self.captured_input_sample_rate = 0.0;
+            ui.label("Analysis window:");
+            egui::ComboBox::new("window", "")
+                .selected_text(self.estimator.window.to_string())
+                .show_ui(ui, |ui| {
+                    for kind in [
+                        Window::Rectangular,
+                        Window::Hann,
+                        Window::Hamming,
+                        Window::BlackmanHarris,
+                        Window::FlatTop,
+                        Window::Kaiser(8.6),
+                        Window::Tukey(0.1),
+                    ] {
+                        if ui
+                            .selectable_label(
+                                std::mem::discriminant(&self.estimator.window)
+                                    == std::mem::discriminant(&kind),
+                                kind.to_string(),
+                            )
+                            .clicked()
+                        {
+                            self.estimator.window = kind;
+                        }
+                    }
+                });
+            match &mut self.estimator.window {
+                Window::Kaiser(beta) => {
+                    ui.label("Beta:");
+                    ui.add(egui::DragValue::new(beta).speed(0.1).range(0.0..=40.0));
+                }
+                Window::Tukey(alpha) => {
+                    ui.label("Alpha:");
+                    ui.add(egui::DragValue::new(alpha).speed(0.01).range(0.0..=1.0));
+                }
+                _ => {}
             }
-            if let Ok(parsed_val) = val.parse::<f32>() {
-                self.captured_input_sample_rate = parsed_val;
-            } else {
-                ui.colored_label(
-                    egui::Color32::RED,
-                    "Invalid input, it should be floating number in the form of 100.0",
+        });
+    }
+
+    /// Returns the band the resonance is searched in: the user band if set, else the
+    /// band swept by the chirp if known, else the whole spectrum.
+    fn resonance_band(&self) -> Band {
+        if self.custom_band {
+            return self.search_band;
+        }
+        match (self.chirp_start, self.chirp_end) {
+            (Some(min), Some(max)) if max > min => Band { min, max },
+            _ => Band::default(),
+        }
+    }
+
+    fn paint_band_input(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if self.is_playing.load(Ordering::SeqCst) {
+                ui.disable();
+            }
+            ui.checkbox(&mut self.custom_band, "Custom search band:");
+            ui.add_enabled(
+                self.custom_band,
+                egui::DragValue::new(&mut self.search_band.min)
+                    .range(0.0..=self.search_band.max)
+                    .suffix(" Hz"),
+            );
+            ui.label("to");
+            ui.add_enabled(
+                self.custom_band,
+                egui::DragValue::new(&mut self.search_band.max)
+                    .range(self.search_band.min..=DEFAULT_SAMPLE_RATE / 2.0)
+                    .suffix(" Hz"),
+            );
+            if !self.custom_band {
+                ui.label("(chirp band)");
+            }
+        });
+    }
+
+    fn paint_estimator_input(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if self.is_playing.load(Ordering::SeqCst) {
+                ui.disable();
+            }
+            ui.label("Peak interpolation:");
+            // Quinn's estimator only holds for the rectangular window without padding.
+            let quinn_valid =
+                self.estimator.window == Window::Rectangular && self.estimator.zero_padding <= 1;
+            egui::ComboBox::new("interpolation", "")
+                .selected_text(format!("{:?}", self.estimator.interpolation))
+                .show_ui(ui, |ui| {
+                    for kind in [
+                        Interpolation::None,
+                        Interpolation::Parabolic,
+                        Interpolation::Gaussian,
+                        Interpolation::Quinn,
+                    ] {
+                        ui.add_enabled_ui(kind != Interpolation::Quinn || quinn_valid, |ui| {
+                            ui.selectable_value(
+                                &mut self.estimator.interpolation,
+                                kind,
+                                format!("{:?}", kind),
+                            )
+                            .on_disabled_hover_text(
+                                "Needs the rectangular window without zero padding",
+                            );
+                        });
+                    }
+                });
+            if self.estimator.effective_interpolation() != self.estimator.interpolation {
+                ui.label("(parabolic: Quinn needs the rectangular window without zero padding)");
+            }
+            ui.label("Zero padding factor:");
+            ui.add(egui::DragValue::new(&mut self.estimator.zero_padding).range(1..=16));
+        });
+        ui.horizontal(|ui| {
+            if self.is_playing.load(Ordering::SeqCst) {
+                ui.disable();
+            }
+            let detection = &mut self.estimator.detection;
+            ui.label("Noise floor percentile:");
+            ui.add(egui::DragValue::new(&mut detection.noise_percentile).range(1.0..=99.0));
+            ui.label("Minimum SNR:");
+            ui.add(
+                egui::DragValue::new(&mut detection.min_snr_db)
+                    .range(0.0..=100.0)
+                    .suffix(" dB"),
+            );
+            ui.label("Minimum level:");
+            ui.add(
+                egui::DragValue::new(&mut detection.min_level_db)
+                    .range(-200.0..=0.0)
+                    .suffix(" dBFS"),
+            );
+        });
+    }
+
+    fn paint_peak_settings_input(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if self.is_playing.load(Ordering::SeqCst) {
+                ui.disable();
+            }
+            ui.label("Peaks:");
+            ui.add(egui::DragValue::new(&mut self.peak_settings.max_peaks).range(1..=50));
+            ui.label("Minimum spacing:");
+            ui.add(
+                egui::DragValue::new(&mut self.peak_settings.min_spacing)
+                    .range(0.0..=10000.0)
+                    .suffix(" Hz"),
+            );
+            ui.label("Threshold:");
+            ui.add(
+                egui::DragValue::new(&mut self.peak_settings.threshold_db)
+                    .range(-200.0..=0.0)
+                    .suffix(" dBFS"),
+            );
+            ui.label("Minimum coherence:");
+            ui.add(
+                egui::DragValue::new(&mut self.peak_settings.min_coherence)
+                    .speed(0.01)
+                    .range(0.0..=1.0),
+            );
+            ui.checkbox(
+                &mut self.peak_settings.reject_incoherent,
+                "Reject incoherent peaks",
+            );
+        });
+    }
+
+    fn paint_frf_settings_input(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if self.is_playing.load(Ordering::SeqCst) {
+                ui.disable();
+            }
+            ui.label("FRF estimator:");
+            egui::ComboBox::new("frf_estimator", "")
+                .selected_text(format!("{:?}", self.frf_settings.estimator))
+                .show_ui(ui, |ui| {
+                    for kind in [FrfEstimator::H1, FrfEstimator::H2] {
+                        ui.selectable_value(
+                            &mut self.frf_settings.estimator,
+                            kind,
+                            format!("{:?}", kind),
+                        );
+                    }
+                });
+            ui.label("Segment length:");
+            egui::ComboBox::new("frf_segment_len", "")
+                .selected_text(format!("{}", self.frf_settings.segment_len))
+                .show_ui(ui, |ui| {
+                    for len in [1024, 4096, 16384, 65536, usize::MAX] {
+                        ui.selectable_value(
+                            &mut self.frf_settings.segment_len,
+                            len,
+                            if len == usize::MAX {
+                                "Whole capture".to_string()
+                            } else {
+                                len.to_string()
+                            },
+                        );
+                    }
+                });
+        });
+    }
+
+    fn paint_welch_settings_input(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if self.is_playing.load(Ordering::SeqCst) {
+                ui.disable();
+            }
+            ui.checkbox(&mut self.use_welch, "Detect peaks on Welch PSD");
+            ui.label("Segment length:");
+            egui::ComboBox::new("welch_segment_len", "")
+                .selected_text(format!("{}", self.welch_settings.segment_len))
+                .show_ui(ui, |ui| {
+                    for len in [1024, 4096, 8192, 16384, 65536, 262144] {
+                        ui.selectable_value(
+                            &mut self.welch_settings.segment_len,
+                            len,
+                            len.to_string(),
+                        );
+                    }
+                });
+            ui.label("Overlap:");
+            ui.add(
+                egui::DragValue::new(&mut self.welch_settings.overlap)
+                    .speed(0.01)
+                    .range(0.0..=0.9),
+            );
+        });
+    }
+
+    fn paint_ringdown_settings_input(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if self.is_playing.load(Ordering::SeqCst) {
+                ui.disable();
+            }
+            ui.label("Ring-down tail:");
+            ui.add(
+                egui::DragValue::new(&mut self.ringdown_tail)
+                    .speed(0.1)
+                    .range(0.0..=10.0)
+                    .suffix(" s"),
+            );
+            ui.label("Band-pass Q:");
+            ui.add(
+                egui::DragValue::new(&mut self.ringdown_settings.filter_q)
+                    .speed(0.1)
+                    .range(0.5..=50.0),
+            );
+        });
+    }
+
+    fn paint_parametric_input(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if self.is_playing.load(Ordering::SeqCst) {
+                ui.disable();
+            }
+            let settings = &mut self.parametric_settings;
+            ui.checkbox(
+                &mut self.use_parametric,
+                "Estimate resonance from the ring-down with",
+            );
+            egui::ComboBox::new("parametric_method", "")
+                .selected_text(settings.method.to_string())
+                .show_ui(ui, |ui| {
+                    for method in [Method::Esprit, Method::RootMusic, Method::MatrixPencil] {
+                        ui.selectable_value(&mut settings.method, method, method.to_string());
+                    }
+                });
+            ui.label("Modes:");
+            ui.add(egui::DragValue::new(&mut settings.modes).range(1..=10));
+            ui.label("Segment length:");
+            egui::ComboBox::new("parametric_segment_len", "")
+                .selected_text(format!("{}", settings.segment_len))
+                .show_ui(ui, |ui| {
+                    for len in [256, 512, 1024, 2048] {
+                        ui.selectable_value(&mut settings.segment_len, len, len.to_string());
+                    }
+                });
+            ui.label("Decimation:");
+            ui.add(egui::DragValue::new(&mut settings.decimation).range(1..=32));
+        });
+    }
+
+    fn paint_refine(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Refine within ±");
+            ui.add(
+                egui::DragValue::new(&mut self.refine_span)
+                    .speed(1.0)
+                    .range(0.1..=1000.0)
+                    .suffix(" Hz"),
+            );
+            ui.label("Points:");
+            ui.add(egui::DragValue::new(&mut self.refine_points).range(16..=100000));
+            if ui.button("Refine").clicked() {
+                self.refine();
+            }
+        });
+        let Some(zoom) = &self.zoom else {
+            return;
+        };
+        ui.label(format!(
+            "Refined frequency of resonance: {:.3} ± {:.3} Hz",
+            zoom.resonance.frequency, zoom.resonance.uncertainty
+        ));
+        let points: Vec<[f64; 2]> = zoom
+            .frequencies
+            .iter()
+            .zip(&zoom.levels_db)
+            .map(|(f, l)| [*f as f64, *l as f64])
+            .collect();
+        Plot::new("Zoom")
+            .height(160.0)
+            .allow_scroll(false)
+            .x_axis_label("Frequency (Hz)")
+            .y_axis_label("Level (dBFS)")
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(points)));
+            });
+    }
+
+    /// Zooms into the spectrum of the capture around the coarse resonance.
+    fn refine(&mut self) {
+        let Ok(Resonance {
+            frequency: center, ..
+        }) = self.last_result.resonance
+        else {
+            self.send_error("no resonance to refine".to_string());
+            return;
+        };
+        let band = Band {
+            min: center - self.refine_span,
+            max: center + self.refine_span,
+        };
+        self.zoom = Some(freq::zoom_resonance(
+            &self.last_result.capture,
+            DEFAULT_SAMPLE_RATE,
+            self.estimator.window,
+            band,
+            self.refine_points,
+        ));
+    }
+
+    fn paint_components(&self, ui: &mut egui::Ui) {
+        if self.last_result.components.is_empty() {
+            return;
+        }
+        ui.label("Modes of the ring-down:");
+        egui::Grid::new("components")
+            .striped(true)
+            .num_columns(4)
+            .show(ui, |ui| {
+                ui.label("Frequency (Hz)");
+                ui.label("Damping ratio");
+                ui.label("Amplitude (dBFS)");
+                ui.label("SNR (dB)");
+                ui.end_row();
+                for component in &self.last_result.components {
+                    ui.label(format!(
+                        "{:.2} ± {:.2}",
+                        component.frequency, component.uncertainty
+                    ));
+                    ui.label(format!("{:.5}", component.damping_ratio));
+                    ui.label(format!(
+                        "{:.1}",
+                        20.0 * component.amplitude.max(f32::MIN_POSITIVE).log10()
+                    ));
+                    ui.label(format!("{:.1}", component.snr_db));
+                    ui.end_row();
+                }
+            });
+    }
+
+    fn paint_ringdown(&self, ui: &mut egui::Ui) {
+        let Some(ringdown) = &self.last_result.ringdown else {
+            ui.label("Ring-down: no decay found after the excitation");
+            return;
+        };
+        ui.label(format!(
+            "Ring-down: time constant {:.2} ms, logarithmic decrement {:.4}, damping ratio {:.4}",
+            ringdown.time_constant * 1000.0,
+            ringdown.log_decrement,
+            ringdown.damping_ratio
+        ));
+        let envelope: Vec<[f64; 2]> = ringdown
+            .envelope
+            .iter()
+            .map(|p| [p[0] as f64, p[1] as f64])
+            .collect();
+        let fit: Vec<[f64; 2]> = ringdown
+            .fit
+            .iter()
+            .map(|p| [p[0] as f64, p[1] as f64])
+            .collect();
+        Plot::new("Ring-down")
+            .height(160.0)
+            .allow_scroll(false)
+            .legend(Legend::default())
+            .x_axis_label("Time (s)")
+            .y_axis_label("Envelope (dB)")
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(envelope)).name("Envelope"));
+                plot_ui.line(Line::new(PlotPoints::new(fit)).name("Exponential fit"));
+            });
+    }
+
+    fn paint_spectrum(&self, ui: &mut egui::Ui) {
+        let psd = &self.last_result.psd;
+        let points: Vec<[f64; 2]> = psd
+            .frequencies
+            .iter()
+            .zip(psd.power_db())
+            .map(|(f, p)| [*f as f64, p as f64])
+            .collect();
+        Plot::new("Spectrum")
+            .height(240.0)
+            .allow_scroll(false)
+            .x_axis_label("Frequency (Hz)")
+            .y_axis_label("PSD (dB/Hz)")
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(points)));
+            });
+    }
+
+    fn paint_octave_bands(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Bands:");
+            egui::ComboBox::new("octave_fraction", "")
+                .selected_text(self.octave_fraction.to_string())
+                .show_ui(ui, |ui| {
+                    for fraction in [
+                        Fraction::Octave,
+                        Fraction::Third,
+                        Fraction::Sixth,
+                        Fraction::Twelfth,
+                    ] {
+                        ui.selectable_value(
+                            &mut self.octave_fraction,
+                            fraction,
+                            fraction.to_string(),
+                        );
+                    }
+                });
+        });
+        let bands = octave::octave_bands(
+            &self.last_result.octave_psd,
+            self.octave_fraction,
+            self.last_result.band,
+            self.weighting,
+        );
+        if bands.is_empty() {
+            ui.label("No band fits the analyzed spectrum");
+            return;
+        }
+        let empty = bands.iter().filter(|b| b.level_db.is_none()).count();
+        if empty > 0 {
+            ui.colored_label(
+                egui::Color32::DARK_RED,
+                format!(
+                    "{} bands are narrower than the resolution of the spectrum and left empty",
+                    empty
+                ),
+            );
+        }
+        // Bars rise from a floor below the quietest band, on a log frequency axis.
+        let floor = bands
+            .iter()
+            .filter_map(|b| b.level_db)
+            .fold(f32::MAX, f32::min)
+            - 10.0;
+        let bars: Vec<Bar> = bands
+            .iter()
+            .filter_map(|band| band.level_db.map(|level| (band, level)))
+            .map(|(band, level)| {
+                let (lower, upper) = (band.lower.log10() as f64, band.upper.log10() as f64);
+                Bar::new((lower + upper) / 2.0, (level - floor) as f64)
+                    .base_offset(floor as f64)
+                    .width((upper - lower) * 0.9)
+                    .name(format!("{:.1} Hz", band.center))
+            })
+            .collect();
+        Plot::new("Octave bands")
+            .height(200.0)
+            .allow_scroll(false)
+            .x_axis_label("Frequency (Hz)")
+            .y_axis_label(format!("Level (dBFS, {}-weighted)", self.weighting))
+            .x_axis_formatter(|mark, _| format!("{:.0}", 10.0_f64.powf(mark.value)))
+            .show(ui, |plot_ui| {
+                plot_ui.bar_chart(BarChart::new(bars));
+            });
+        if ui.button("Export bands to CSV").clicked() {
+            let (header, rows) = octave::table(&bands, self.weighting);
+            self.export_csv("octave_bands.csv", header, rows);
+        }
+    }
+
+    fn paint_instantaneous(&mut self, ui: &mut egui::Ui) {
+        let instantaneous = &self.last_result.instantaneous;
+        // Every sample is too many points to plot, a few thousand are kept.
+        let step = (instantaneous.envelope.len() / 4000).max(1);
+        let time = |i: usize| (i as f32 / instantaneous.sample_rate) as f64;
+        let envelope: Vec<[f64; 2]> = instantaneous
+            .envelope
+            .iter()
+            .enumerate()
+            .step_by(step)
+            .map(|(i, e)| [time(i), 20.0 * e.max(f32::MIN_POSITIVE).log10() as f64])
+            .collect();
+        let frequency: Vec<[f64; 2]> = instantaneous
+            .frequency
+            .iter()
+            .enumerate()
+            .step_by(step)
+            .map(|(i, f)| [time(i), *f as f64])
+            .collect();
+        Plot::new("Envelope")
+            .height(160.0)
+            .allow_scroll(false)
+            .link_axis("instantaneous", true, false)
+            .x_axis_label("Time (s)")
+            .y_axis_label("Envelope (dBFS)")
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(envelope)));
+            });
+        Plot::new("Instantaneous frequency")
+            .height(160.0)
+            .allow_scroll(false)
+            .link_axis("instantaneous", true, false)
+            .x_axis_label("Time (s)")
+            .y_axis_label("Frequency (Hz)")
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(frequency)));
+            });
+
+        ui.horizontal(|ui| {
+            ui.label("Envelope against instantaneous frequency, bins:");
+            ui.add(egui::DragValue::new(&mut self.response_curve_bins).range(10..=10000));
+        });
+        let curve = self
+            .last_result
+            .instantaneous
+            .response_curve(self.last_result.band, self.response_curve_bins);
+        let points: Vec<[f64; 2]> = curve.iter().map(|p| [p[0] as f64, p[1] as f64]).collect();
+        Plot::new("Envelope response")
+            .height(200.0)
+            .allow_scroll(false)
+            .x_axis_label("Instantaneous frequency (Hz)")
+            .y_axis_label("Envelope (dBFS)")
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(points)));
+            });
+        if ui.button("Export envelope response to CSV").clicked() {
+            let header = vec![
+                "Instantaneous frequency (Hz)".to_string(),
+                "Envelope (dBFS)".to_string(),
+            ];
+            let rows = curve.iter().map(|p| p.to_vec()).collect();
+            self.export_csv("envelope_response.csv", header, rows);
+        }
+    }
+
+    fn paint_frequency_response(&self, ui: &mut egui::Ui) {
+        let frf = &self.last_result.frf;
+        let magnitude: Vec<[f64; 2]> = frf
+            .frequencies
+            .iter()
+            .zip(frf.magnitude_db())
+            .map(|(f, m)| [*f as f64, m as f64])
+            .collect();
+        let phase: Vec<[f64; 2]> = frf
+            .frequencies
+            .iter()
+            .zip(frf.phase_deg())
+            .map(|(f, p)| [*f as f64, p as f64])
+            .collect();
+        // The synthesized curve of the modal fit, if any, is drawn over the measurement.
+        let synthesized = self.modal_fit.as_ref().map(|fit| &fit.synthesized);
+        let fit_magnitude: Option<Vec<[f64; 2]>> = synthesized.map(|frf| {
+            frf.frequencies
+                .iter()
+                .zip(frf.magnitude_db())
+                .map(|(f, m)| [*f as f64, m as f64])
+                .collect()
+        });
+        let fit_phase: Option<Vec<[f64; 2]>> = synthesized.map(|frf| {
+            frf.frequencies
+                .iter()
+                .zip(frf.phase_deg())
+                .map(|(f, p)| [*f as f64, p as f64])
+                .collect()
+        });
+        Plot::new("FRF magnitude")
+            .height(240.0)
+            .allow_scroll(false)
+            .legend(Legend::default())
+            .link_axis("frf", true, false)
+            .x_axis_label("Frequency (Hz)")
+            .y_axis_label("|H| (dB)")
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(magnitude)).name("Measured"));
+                if let Some(points) = fit_magnitude {
+                    plot_ui.line(Line::new(PlotPoints::new(points)).name("Modal fit"));
+                }
+            });
+        Plot::new("FRF phase")
+            .height(160.0)
+            .allow_scroll(false)
+            .legend(Legend::default())
+            .link_axis("frf", true, false)
+            .x_axis_label("Frequency (Hz)")
+            .y_axis_label("Phase (°)")
+            .include_y(-180.0)
+            .include_y(180.0)
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(phase)).name("Measured"));
+                if let Some(points) = fit_phase {
+                    plot_ui.line(Line::new(PlotPoints::new(points)).name("Modal fit"));
+                }
+            });
+        let coherence: Vec<[f64; 2]> = frf
+            .frequencies
+            .iter()
+            .zip(&frf.coherence)
+            .map(|(f, c)| [*f as f64, *c as f64])
+            .collect();
+        Plot::new("FRF coherence")
+            .height(120.0)
+            .allow_scroll(false)
+            .link_axis("frf", true, false)
+            .x_axis_label("Frequency (Hz)")
+            .y_axis_label("Coherence")
+            .include_y(0.0)
+            .include_y(1.0)
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(coherence)));
+                plot_ui.hline(
+                    HLine::new(self.peak_settings.min_coherence as f64)
+                        .color(egui::Color32::DARK_RED),
                 );
+            });
+    }
+
+    fn paint_modal_fit(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Fit modes from");
+            ui.add(
+                egui::DragValue::new(&mut self.modal_settings.band.min)
+                    .range(0.0..=self.modal_settings.band.max)
+                    .suffix(" Hz"),
+            );
+            ui.label("to");
+            ui.add(
+                egui::DragValue::new(&mut self.modal_settings.band.max)
+                    .range(self.modal_settings.band.min..=DEFAULT_SAMPLE_RATE / 2.0)
+                    .suffix(" Hz"),
+            );
+            ui.label("Modes:");
+            ui.add(egui::DragValue::new(&mut self.modal_settings.modes).range(1..=8));
+            if ui.button("Fit modes").clicked() {
+                self.modal_fit = modal::fit(&self.last_result.frf, &self.modal_settings);
+                if self.modal_fit.is_none() {
+                    self.send_error("too few FRF bins in the band to fit the modes".to_string());
+                }
+            }
+        });
+        let Some(fit) = &self.modal_fit else {
+            return;
+        };
+        ui.label(format!(
+            "Modal fit error: {:.2} % of the measured response",
+            fit.error * 100.0
+        ));
+        egui::Grid::new("modes").striped(true).show(ui, |ui| {
+            for title in ["Mode", "Frequency", "Damping ratio", "Q", "Residue"] {
+                ui.label(title);
+            }
+            ui.end_row();
+            for (i, mode) in fit.modes.iter().enumerate() {
+                ui.label(format!("{}", i + 1));
+                ui.label(format!("{:.2} Hz", mode.frequency));
+                ui.label(format!("{:.5}", mode.damping_ratio));
+                ui.label(format!("{:.1}", 1.0 / (2.0 * mode.damping_ratio)));
+                ui.label(format!("{:.4} {:+.4}j", mode.residue.re, mode.residue.im));
+                ui.end_row();
             }
         });
+        if ui.button("Export modes to CSV").clicked() {
+            let (header, rows) = fit.table();
+            self.export_csv("modes.csv", header, rows);
+        }
+    }
+
+    fn paint_sweep_response(&mut self, ui: &mut egui::Ui) {
+        let Some(response) = &self.last_result.sweep else {
+            return;
+        };
+        let to_points = |frf: &Frf| -> Vec<[f64; 2]> {
+            frf.frequencies
+                .iter()
+                .zip(frf.magnitude_db())
+                .map(|(f, m)| [*f as f64, m as f64])
+                .collect()
+        };
+        let linear = to_points(&response.linear);
+        let harmonics: Vec<Vec<[f64; 2]>> = response.harmonics.iter().map(to_points).collect();
+        Plot::new("Sweep response")
+            .height(240.0)
+            .allow_scroll(false)
+            .legend(Legend::default())
+            .x_axis_label("Excitation frequency (Hz)")
+            .y_axis_label("Magnitude (dB)")
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(linear)).name("H1"));
+                for (i, points) in harmonics.into_iter().enumerate() {
+                    plot_ui.line(Line::new(PlotPoints::new(points)).name(format!("H{}", i + 2)));
+                }
+            });
+        if ui.button("Export sweep to CSV").clicked() {
+            let (header, rows) = response.table();
+            self.export_csv("sweep.csv", header, rows);
+        }
+    }
+
+    /// Asks for a path and saves `rows` under `header` as CSV in the background.
+    fn export_csv(&mut self, file_name: &str, header: Vec<String>, rows: Vec<Vec<f32>>) {
+        let Some(path) = rfd::FileDialog::new()
+            .set_file_name(file_name)
+            .set_can_create_directories(true)
+            .save_file()
+        else {
+            return;
+        };
+        let metadata = vec![format!("Preprocessing: {}", self.last_result.preprocess)];
+        let tx = self.status_tx.clone();
+        self.tasker.spawn(async move {
+            tx.send("Saving csv file".to_string())
+                .await
+                .unwrap_or_else(|e| eprintln!("{}", e));
+            audio::save_columns_to_csv(&metadata, &header, &rows, &path)
+                .await
+                .unwrap_or_else(|e| eprintln!("{}", e));
+            tx.send("Done saving csv file".to_string())
+                .await
+                .unwrap_or_else(|e| eprintln!("{}", e));
+        });
+    }
+
+    /// Returns the preprocessing of the last analysis, to record with the exported
+    /// capture, which is saved as it was recorded.
+    fn capture_comment(&self) -> String {
+        format!(
+            "Preprocessing before the analysis, not applied to these samples: {}",
+            self.last_result.preprocess
+        )
+    }
+
+    /// Returns the excitation shifted by the measured latency, from `start` seconds
+    /// on, to overlay on the capture once it is analyzed.
+    fn excitation_overlay(&self, start: f64) -> Vec<[f64; 2]> {
+        if self.is_playing.load(Ordering::SeqCst) {
+            return Vec::new();
+        }
+        let (Some(chirp), Some(latency)) = (&self.current_chirp, self.last_result.latency) else {
+            return Vec::new();
+        };
+        chirp
+            .samples
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                [
+                    (i as f32 / chirp.sample_rate + latency.seconds) as f64,
+                    *x as f64,
+                ]
+            })
+            .filter(|p| p[0] >= start)
+            .collect()
     }
 
     fn paint_sound_devices_dropdown(&mut self, ui: &mut egui::Ui) -> Result<()> {
@@ -378,31 +1476,27 @@ impl CalibrateTab {
                             .save_file()
                         {
                             let tx = self.status_tx.clone();
-                            let captured_buffer = match self.current_chirp.clone() {
-                                Some(v) => v.samples,
+                            let (captured_buffer, sample_rate) = match self.current_chirp.clone() {
+                                Some(v) => (v.samples, v.sample_rate as u32),
                                 None => {
                                     self.send_error("empty chirp buffer".to_string());
                                     return;
                                 }
                             };
-                            let sample_rate = self.captured_input_sample_rate as u32;
                             self.tasker.spawn(async move {
                                 tx.send("Saving wav file".to_string())
                                     .await
                                     .unwrap_or_else(|e| {
                                         eprintln!("error: {}", e);
-                                        return;
                                     });
                                 audio::save_mono_vec_to_wav(&captured_buffer, sample_rate, &path)
                                     .unwrap_or_else(|e| {
                                         eprintln!("error: {}", e);
-                                        return;
                                     });
                                 tx.send("Done saving wav file".to_string())
                                     .await
                                     .unwrap_or_else(|e| {
                                         eprintln!("error: {}", e);
-                                        return;
                                     });
                             });
                         }
@@ -414,36 +1508,34 @@ impl CalibrateTab {
                             .save_file()
                         {
                             let tx = self.status_tx.clone();
-                            let captured_buffer = match self.current_chirp.clone() {
-                                Some(v) => v.samples,
+                            let (captured_buffer, sample_rate) = match self.current_chirp.clone() {
+                                Some(v) => (v.samples, v.sample_rate as u32),
                                 None => {
                                     self.send_error("failed to save captured buffer".to_string());
                                     return;
                                 }
                             };
-                            let sample_rate = self.captured_input_sample_rate as u32;
+                            let weighting = self.weighting;
                             self.tasker.spawn(async move {
                                 tx.send("Saving csv file".to_string())
                                     .await
                                     .unwrap_or_else(|e| {
                                         eprintln!("error: {}", e);
-                                        return;
                                     });
                                 audio::save_mono_vec_with_db_to_csv(
                                     &captured_buffer,
                                     sample_rate,
+                                    weighting,
                                     &path,
                                 )
                                 .await
                                 .unwrap_or_else(|e| {
                                     eprintln!("{}", e);
-                                    return;
                                 });
                                 tx.send("Done saving csv file".to_string())
                                     .await
                                     .unwrap_or_else(|e| {
                                         eprintln!("error: {}", e);
-                                        return;
                                     });
                             });
                         }
@@ -453,8 +1545,189 @@ impl CalibrateTab {
         });
     }
 
-    fn paint_frequency_of_resonance(&self, ui: &mut egui::Ui) {
-        ui.label(format!("Frequency of resonance: {:.2} Hz", self.last_for));
+    fn paint_level(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Weighting:");
+            egui::ComboBox::new("calibrate_weighting", "")
+                .selected_text(self.weighting.to_string())
+                .show_ui(ui, |ui| {
+                    for weighting in [Weighting::A, Weighting::C, Weighting::Z] {
+                        ui.selectable_value(&mut self.weighting, weighting, weighting.to_string());
+                    }
+                });
+            // Filtering the whole capture is too slow to redo every frame.
+            let level = match self.weighted_level {
+                Some((weighting, level)) if weighting == self.weighting => level,
+                _ => {
+                    let level = self
+                        .weighting
+                        .level_db(&self.last_result.capture, DEFAULT_SAMPLE_RATE);
+                    self.weighted_level = Some((self.weighting, level));
+                    level
+                }
+            };
+            ui.label(format!(
+                "Level: {:.1} dBFS, {}-weighted",
+                level, self.weighting
+            ));
+        });
+    }
+
+    fn paint_frequency_of_resonance(&mut self, ui: &mut egui::Ui) {
+        self.paint_level(ui);
+        ui.horizontal(|ui| {
+            match self.last_result.resonance {
+                Ok(resonance) => ui.label(format!(
+                    "Frequency of resonance: {:.2} ± {:.2} Hz, {:.1} dB above the noise floor",
+                    resonance.frequency, resonance.uncertainty, resonance.snr_db
+                )),
+                Err(reason) => ui.colored_label(
+                    egui::Color32::DARK_RED,
+                    format!("No resonance detected: {}", reason),
+                ),
+            };
+            let band = self.last_result.band;
+            if band != Band::default() {
+                ui.label(format!("in {:.0} - {:.0} Hz", band.min, band.max));
+            }
+            let peak = self.last_result.resonance.ok().and_then(|resonance| {
+                peaks::at_frequency(&self.last_result.peaks, resonance.frequency)
+            });
+            if let Some(peak) = peak {
+                ui.label(format!(
+                    "Q: {:.1}, damping ratio: {:.4}",
+                    peak.damping.q, peak.damping.ratio
+                ));
+                if !peak.damping.in_band {
+                    ui.colored_label(
+                        egui::Color32::DARK_RED,
+                        "(-3 dB point outside the analyzed band)",
+                    );
+                }
+            }
+        });
+
+        ui.label(format!("Preprocessing: {}", self.last_result.preprocess));
+        ui.label(format!("Analyzed channels: {}", self.last_result.channels));
+        if let Some(stream_sample_rate) = self.last_result.stream_sample_rate {
+            if stream_sample_rate != DEFAULT_SAMPLE_RATE {
+                ui.colored_label(
+                    egui::Color32::DARK_RED,
+                    format!(
+                        "The input streamed at {} Hz and was resampled to {} Hz for the analysis",
+                        stream_sample_rate, DEFAULT_SAMPLE_RATE
+                    ),
+                );
+            }
+        }
+        match self.last_result.latency {
+            Some(latency) => ui.label(format!(
+                "Latency: {:.3} ms ({:.1} samples), correlation {:.2}",
+                latency.seconds * 1000.0,
+                latency.samples,
+                latency.correlation
+            )),
+            None => ui.label("Latency: excitation not found in the capture"),
+        };
+        self.paint_refine(ui);
+        self.paint_components(ui);
+        self.paint_ringdown(ui);
+
+        let mut peaks = self.last_result.peaks.clone();
+        peaks.sort_by(|a, b| {
+            let ordering = match self.peak_sort {
+                PeakColumn::Frequency => a.frequency.total_cmp(&b.frequency),
+                PeakColumn::Magnitude => a.magnitude_db.total_cmp(&b.magnitude_db),
+                PeakColumn::Prominence => a.prominence.total_cmp(&b.prominence),
+                PeakColumn::Bandwidth => a.damping.bandwidth.total_cmp(&b.damping.bandwidth),
+                PeakColumn::Q => a.damping.q.total_cmp(&b.damping.q),
+                PeakColumn::DampingRatio => a.damping.ratio.total_cmp(&b.damping.ratio),
+                PeakColumn::Snr => a.snr_db.total_cmp(&b.snr_db),
+                // Peaks without a coherence sort before all the others.
+                PeakColumn::Coherence => match (a.coherence, b.coherence) {
+                    (Some(a), Some(b)) => a.total_cmp(&b),
+                    (a, b) => a.is_some().cmp(&b.is_some()),
+                },
+            };
+            if self.peak_sort_descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+
+        egui::Grid::new("peaks")
+            .striped(true)
+            .num_columns(8)
+            .show(ui, |ui| {
+                for (column, title) in [
+                    (PeakColumn::Frequency, "Frequency (Hz)"),
+                    (PeakColumn::Magnitude, "Magnitude (dBFS)"),
+                    (PeakColumn::Prominence, "Prominence (dB)"),
+                    (PeakColumn::Bandwidth, "-3 dB bandwidth (Hz)"),
+                    (PeakColumn::Q, "Q"),
+                    (PeakColumn::DampingRatio, "Damping ratio"),
+                    (PeakColumn::Snr, "SNR (dB)"),
+                    (PeakColumn::Coherence, "Coherence"),
+                ] {
+                    let title = if self.peak_sort == column {
+                        format!(
+                            "{} {}",
+                            title,
+                            if self.peak_sort_descending {
+                                "⏷"
+                            } else {
+                                "⏶"
+                            }
+                        )
+                    } else {
+                        title.to_string()
+                    };
+                    if ui.button(title).clicked() {
+                        if self.peak_sort == column {
+                            self.peak_sort_descending = !self.peak_sort_descending;
+                        } else {
+                            self.peak_sort = column;
+                            self.peak_sort_descending = true;
+                        }
+                    }
+                }
+                ui.end_row();
+                for peak in peaks {
+                    let frequency = format!("{:.2}", peak.frequency);
+                    if peak.is_incoherent(&self.peak_settings) {
+                        ui.colored_label(egui::Color32::DARK_RED, format!("{} ⚠", frequency))
+                            .on_hover_text(
+                                "Coherence below the threshold, likely not caused by the chirp",
+                            );
+                    } else {
+                        ui.label(frequency);
+                    }
+                    ui.label(format!("{:.1}", peak.magnitude_db));
+                    ui.label(format!("{:.1}", peak.prominence));
+                    let bandwidth = format!("{:.2}", peak.damping.bandwidth);
+                    if peak.damping.in_band {
+                        ui.label(bandwidth);
+                    } else {
+                        ui.colored_label(egui::Color32::DARK_RED, format!("{} ⚠", bandwidth))
+                            .on_hover_text("-3 dB point outside the analyzed band");
+                    }
+                    ui.label(format!("{:.1}", peak.damping.q));
+                    ui.label(format!("{:.4}", peak.damping.ratio));
+                    let snr = format!("{:.1}", peak.snr_db);
+                    if peak.is_below_noise(&self.estimator.detection) {
+                        ui.colored_label(egui::Color32::DARK_RED, format!("{} ⚠", snr))
+                            .on_hover_text("Too close to the noise floor to be a resonance");
+                    } else {
+                        ui.label(snr);
+                    }
+                    ui.label(
+                        peak.coherence
+                            .map_or("-".to_string(), |c| format!("{:.2}", c)),
+                    );
+                    ui.end_row();
+                }
+            });
     }
 
     fn update_outgoing_wave_graph(&mut self) -> Result<()> {
@@ -486,8 +1759,8 @@ impl CalibrateTab {
             points.push([time as f64, val as f64]);
         }
         self.points_vector = points;
-        // Stop playing after 15 seconds
-        if elapsed >= self.duration.ok_or("duration is null")? {
+        // Stop playing once the chirp and the ring-down tail are captured.
+        if elapsed >= self.duration.ok_or("duration is null")? + self.ringdown_tail {
             self.is_playing.store(false, Ordering::SeqCst);
             self.started_sound = false;
         }
@@ -508,8 +1781,9 @@ impl CalibrateTab {
                     self.paint_chirp_start_input(ui);
                     self.paint_chirp_end_input(ui);
                     self.paint_output_sample_rate_input(ui);
-                    self.paint_captured_input_sample_rate(ui);
+                    self.paint_stream_sample_rate(ui);
                     self.paint_input_file_input(ui);
+                    self.paint_sweep_generator_input(ui);
                 });
             });
         });
@@ -521,6 +1795,26 @@ impl CalibrateTab {
                     self.paint_sound_devices_dropdown(ui)
                         .unwrap_or_else(|e| self.send_error(e.to_string()));
                     self.paint_drain_graphs_checkbox(ui);
+                    self.paint_window_input(ui);
+                    self.paint_estimator_input(ui);
+                    self.paint_band_input(ui);
+                    self.paint_peak_settings_input(ui);
+                    self.paint_frf_settings_input(ui);
+                    self.paint_welch_settings_input(ui);
+                    self.paint_ringdown_settings_input(ui);
+                    self.paint_parametric_input(ui);
+                    self.preprocess
+                        .paint_settings(ui, self.is_playing.load(Ordering::SeqCst));
+                    let channel_count = self
+                        .captured_buffer
+                        .lock()
+                        .map_or(1, |capture| capture.channels.len());
+                    self.channels.paint_settings(
+                        ui,
+                        "Analyzed channels:",
+                        channel_count,
+                        self.is_playing.load(Ordering::SeqCst),
+                    );
                     self.paint_start_and_stop_buttons(ui)
                         .unwrap_or_else(|e| self.send_error(e.to_string()));
                 });
@@ -537,6 +1831,37 @@ impl CalibrateTab {
                 }
             });
         });
+        if !self.is_playing.load(Ordering::SeqCst) && !self.last_result.psd.power.is_empty() {
+            ui.add_space(20.0);
+            egui::Frame::group(ui.style()).show(ui, |ui| {
+                ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
+                    ui.label(egui::RichText::new("Spectrum (Welch)"));
+                    self.paint_spectrum(ui);
+                    ui.label(egui::RichText::new("Fractional-octave bands"));
+                    self.paint_octave_bands(ui);
+                });
+            });
+        }
+        if !self.is_playing.load(Ordering::SeqCst)
+            && !self.last_result.instantaneous.envelope.is_empty()
+        {
+            ui.add_space(20.0);
+            egui::Frame::group(ui.style()).show(ui, |ui| {
+                ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
+                    ui.label(egui::RichText::new("Envelope and instantaneous frequency"));
+                    self.paint_instantaneous(ui);
+                });
+            });
+        }
+        if !self.is_playing.load(Ordering::SeqCst) && self.last_result.sweep.is_some() {
+            ui.add_space(20.0);
+            egui::Frame::group(ui.style()).show(ui, |ui| {
+                ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
+                    ui.label(egui::RichText::new("Exponential sweep response"));
+                    self.paint_sweep_response(ui);
+                });
+            });
+        }
         ui.add_space(20.0);
         self.paint_output_wave(ui);
 
@@ -547,49 +1872,114 @@ impl CalibrateTab {
             ui.label("Calculating frequency of resonance...");
         }
 
-        let mut buffer_to_plot = Vec::new();
+        let mut capture = Capture::default();
         {
             if let Ok(captured_buffer) = self.captured_buffer.lock() {
-                if let Ok(freq) = self.for_rx.try_recv() {
-                    self.last_for = freq;
+                if let Ok(result) = self.for_rx.try_recv() {
+                    self.last_result = result;
+                    self.zoom = None;
+                    self.weighted_level = None;
+                    self.modal_fit = None;
                 }
-                buffer_to_plot = captured_buffer.clone();
+                capture = captured_buffer.clone();
             };
         }
 
-        let buf_len = buffer_to_plot.len();
-
-        let mut points: Vec<[f64; 2]> = buffer_to_plot
+        // The analysis rate only stands in until the input has streamed.
+        let sample_rate = if capture.sample_rate > 0.0 {
+            capture.sample_rate
+        } else {
+            DEFAULT_SAMPLE_RATE
+        };
+        self.spectrogram
+            .update(&capture.mix(self.channels), sample_rate);
+        // Once the capture is analyzed, plot it as it was analyzed, else plot every
+        // channel.
+        let traces: Vec<(String, Vec<f32>, f32)> = if !self.is_playing.load(Ordering::SeqCst)
+            && self.last_result.capture.len()
+                == resample::resampled_len(capture.len(), sample_rate, DEFAULT_SAMPLE_RATE)
+        {
+            vec![(
+                "Capture".to_string(),
+                self.last_result.capture.clone(),
+                DEFAULT_SAMPLE_RATE,
+            )]
+        } else {
+            capture
+                .channels
+                .into_iter()
+                .enumerate()
+                .map(|(i, channel)| (format!("Channel {}", i + 1), channel, sample_rate))
+                .collect()
+        };
+        let lines: Vec<(String, Vec<[f64; 2]>)> = traces
             .into_iter()
-            .enumerate()
-            .map(|(i, x)| {
-                [
-                    (i as f32 / self.captured_input_sample_rate) as f64,
-                    x as f64,
-                ]
+            .map(|(name, samples, sample_rate)| {
+                let mut points: Vec<[f64; 2]> = samples
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| [(i as f32 / sample_rate) as f64, *x as f64])
+                    .collect();
+                let max_len = sample_rate as usize * 5;
+                if self.drain_graphs && points.len() > max_len {
+                    points.drain(0..points.len() - max_len);
+                }
+                (name, points)
             })
             .collect();
 
-        if self.drain_graphs {
-            if buf_len > self.captured_input_sample_rate as usize * 5 {
-                points.drain(0..buf_len - self.captured_input_sample_rate as usize * 5);
-            }
-        }
-
         ui.add_space(20.0);
         egui::Frame::group(ui.style()).show(ui, |ui| {
             ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
-                ui.label(egui::RichText::new("Captured Input"));
-                let line = Line::new(PlotPoints::new(points));
-                let plot = Plot::new("Received audio")
-                    .allow_scroll(false)
-                    .height(240.0);
-                plot.show(ui, |plot_ui| {
-                    plot_ui.line(line);
-                });
+                let show_frf = !self.is_playing.load(Ordering::SeqCst)
+                    && !self.show_raw_capture
+                    && !self.last_result.frf.response.is_empty();
+                if show_frf {
+                    ui.label(egui::RichText::new("Frequency response"));
+                    self.paint_frequency_response(ui);
+                    self.paint_modal_fit(ui);
+                } else {
+                    ui.label(egui::RichText::new("Captured Input"));
+                    let start = lines
+                        .first()
+                        .and_then(|(_, points)| points.first())
+                        .map_or(0.0, |p| p[0]);
+                    let overlay = self.excitation_overlay(start);
+                    let plot = Plot::new("Received audio")
+                        .allow_scroll(false)
+                        .legend(Legend::default())
+                        .height(240.0);
+                    plot.show(ui, |plot_ui| {
+                        for (name, points) in lines {
+                            plot_ui.line(Line::new(PlotPoints::new(points)).name(name));
+                        }
+                        if !overlay.is_empty() {
+                            plot_ui.line(
+                                Line::new(PlotPoints::new(overlay))
+                                    .name("Excitation, delayed by the latency"),
+                            );
+                        }
+                    });
+                }
+                ui.checkbox(&mut self.show_raw_capture, "Show raw capture");
+                ui.label(egui::RichText::new("Spectrogram"));
+                self.spectrogram
+                    .paint_settings(ui, self.is_playing.load(Ordering::SeqCst));
+                self.spectrogram.show(ui);
                 if self.is_playing.load(Ordering::SeqCst) {
                     ui.disable();
                 }
+                let channel_count = self
+                    .captured_buffer
+                    .lock()
+                    .map_or(1, |capture| capture.channels.len());
+                self.export_channels.paint_settings(
+                    ui,
+                    "Exported channels:",
+                    channel_count,
+                    self.is_playing.load(Ordering::SeqCst),
+                );
+                let export_channels = self.export_channels;
                 ui.horizontal(|ui| {
                     if ui.button("Export to wav").clicked {
                         if let Some(path) = rfd::FileDialog::new()
@@ -598,30 +1988,28 @@ impl CalibrateTab {
                             .save_file()
                         {
                             let tx = self.status_tx.clone();
+                            let comment = self.capture_comment();
                             if let Ok(captured_buffer) = self.captured_buffer.lock() {
                                 let captured_buffer = captured_buffer.clone();
-                                let sample_rate = self.captured_input_sample_rate as u32;
                                 self.tasker.spawn(async move {
                                     tx.send("Saving wav file".to_string()).await.unwrap_or_else(
                                         |e| {
                                             eprintln!("{}", e);
-                                            return;
                                         },
                                     );
-                                    audio::save_mono_vec_to_wav(
+                                    audio::save_capture_to_wav(
                                         &captured_buffer,
-                                        sample_rate,
+                                        export_channels,
+                                        &comment,
                                         &path,
                                     )
                                     .unwrap_or_else(|e| {
                                         eprintln!("{}", e);
-                                        return;
                                     });
                                     tx.send("Done saving wav file".to_string())
                                         .await
                                         .unwrap_or_else(|e| {
                                             eprintln!("{}", e);
-                                            return;
                                         });
                                 });
                             };
@@ -634,31 +2022,31 @@ impl CalibrateTab {
                             .save_file()
                         {
                             let tx = self.status_tx.clone();
+                            let metadata = vec![self.capture_comment()];
                             if let Ok(captured_buffer) = self.captured_buffer.lock() {
                                 let captured_buffer = captured_buffer.clone();
-                                let sample_rate = self.captured_input_sample_rate as u32;
+                                let weighting = self.weighting;
                                 self.tasker.spawn(async move {
                                     tx.send("Saving csv file".to_string()).await.unwrap_or_else(
                                         |e| {
                                             eprintln!("{}", e);
-                                            return;
                                         },
                                     );
-                                    audio::save_mono_vec_with_db_to_csv(
+                                    audio::save_capture_with_db_to_csv(
                                         &captured_buffer,
-                                        sample_rate,
+                                        export_channels,
+                                        weighting,
+                                        &metadata,
                                         &path,
                                     )
                                     .await
                                     .unwrap_or_else(|e| {
                                         eprintln!("{}", e);
-                                        return;
                                     });
                                     tx.send("Done saving csv file".to_string())
                                         .await
                                         .unwrap_or_else(|e| {
                                             eprintln!("{}", e);
-                                            return;
                                         });
                                 });
                             };
@@ -1,6 +1,8 @@
 use rodio::source::Source;
 use std::time::Duration;
 
+use crate::sweep::SweepSettings;
+
 /// Chirp is a linear sound wave which frequency increases linearly over time.
 #[derive(Debug, Clone)]
 pub struct Chirp {
@@ -10,6 +12,23 @@ pub struct Chirp {
     pub sample_rate: f32,
     index: usize,
     pub samples: Vec<f32>,
+    /// Settings of the exponential sweep the samples were generated from, if any.
+    pub sweep: Option<SweepSettings>,
+}
+
+impl Chirp {
+    /// Generates an exponential sine sweep.
+    pub fn exponential(settings: SweepSettings, sample_rate: f32) -> Self {
+        Self {
+            start_freq: settings.start_freq,
+            end_freq: settings.end_freq,
+            duration: settings.duration,
+            sample_rate,
+            index: 0,
+            samples: settings.samples(sample_rate),
+            sweep: Some(settings),
+        }
+    }
 }
 
 impl TryFrom<hound::WavReader<std::io::BufReader<std::fs::File>>> for Chirp {
@@ -35,9 +54,10 @@ impl TryFrom<hound::WavReader<std::io::BufReader<std::fs::File>>> for Chirp {
             samples,
             sample_rate: sample_rate as f32,
             duration: duration as f32,
-            start_freq: start_freq,
+            start_freq,
             end_freq: end_freq.to_owned(),
             index: 0,
+            sweep: None,
         })
     }
 }
@@ -45,7 +65,7 @@ impl TryFrom<hound::WavReader<std::io::BufReader<std::fs::File>>> for Chirp {
 impl Iterator for Chirp {
     type Item = f32;
     fn next(&mut self) -> Option<f32> {
-        let index = self.index as usize;
+        let index = self.index;
         if index < self.samples.len() {
             let sample = self.samples[index];
             self.index += 1;
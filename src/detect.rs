@@ -1,6 +1,14 @@
-use crate::audio;
+use crate::audio::{self, Capture, Channels};
+use crate::distortion::{self, Distortion, DistortionSettings};
+use crate::freq::{self, Estimator, Interpolation, NoResonance, Resonance};
+use crate::goertzel::{Goertzel, Tone};
+use crate::preprocess::Preprocess;
+use crate::resample;
+use crate::spectrogram::SpectrogramView;
+use crate::weighting::Weighting;
+use crate::window::Window;
 use cpal::traits::DeviceTrait;
-use egui_plot::{Line, Plot, PlotPoints};
+use egui_plot::{Legend, Line, Plot, PlotPoints};
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{
@@ -13,19 +21,49 @@ use tokio::sync::mpsc::Sender as TSender;
 
 use crate::utils::Result;
 
-#[derive(Debug)]
+/// Storage key of the preprocessing chain.
+const PREPROCESS_KEY: &str = "detect_preprocess";
+
+/// DetectionResult is the analysis of one capture.
+#[derive(Debug, Clone)]
+struct DetectionResult {
+    resonance: std::result::Result<Resonance, NoResonance>,
+    distortion: Distortion,
+    /// Weighted level of the capture in dBFS.
+    level_db: f32,
+    weighting: Weighting,
+    /// Sample rate the input device streamed at, resampled to the captured sample
+    /// rate before the analysis.
+    stream_sample_rate: f32,
+    /// Preprocessing applied to the capture before the analysis.
+    preprocess: Preprocess,
+}
+
 pub struct DetectTab {
     sine_wave_freq: f32,
     output_sample_rate: f32,
     captured_sample_rate: f32,
     duration: f32,
     sine_wave: crate::wave::Wave,
-    captured_buffer: Arc<Mutex<Vec<f32>>>,
+    captured_buffer: Arc<Mutex<Capture>>,
+    channels: Channels,
+    /// Channels written by the capture exports.
+    export_channels: Channels,
     points_vector: Vec<[f64; 2]>,
+    spectrogram: SpectrogramView,
     down_sample_factor: f32,
     start_time: Instant,
-    for_tx: Sender<f32>,
-    _for_rx: Receiver<f32>,
+    for_tx: Sender<DetectionResult>,
+    for_rx: Receiver<DetectionResult>,
+    last_result: Option<DetectionResult>,
+    distortion_settings: DistortionSettings,
+    preprocess: Preprocess,
+    weighting: Weighting,
+    /// Tone detector fed with the capture as it arrives.
+    detector: Option<Goertzel>,
+    /// Length of the detector blocks in seconds.
+    detector_block: f32,
+    tones: Vec<Tone>,
 
     status_tx: TSender<String>,
 
@@ -40,13 +78,15 @@ pub struct DetectTab {
 }
 
 impl DetectTab {
-    pub fn new(status_tx: TSender<String>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, status_tx: TSender<String>) -> Self {
         let sine_wave_freq: f32 = 441.0; // Default to A4 note.
-        let (for_tx, _for_rx): (Sender<f32>, Receiver<f32>) = mpsc::channel();
+        let (for_tx, for_rx): (Sender<DetectionResult>, Receiver<DetectionResult>) =
+            mpsc::channel();
 
         Self {
             sine_wave_freq,
             points_vector: Vec::new(),
+            spectrogram: SpectrogramView::new("detect_spectrogram"),
             output_sample_rate: 192000.0,
             captured_sample_rate: 192000.0,
             down_sample_factor: 1000.0,
@@ -58,14 +98,31 @@ impl DetectTab {
             is_playing: Arc::new(AtomicBool::new(false)),
             started_playing: false,
             sine_wave: crate::wave::Wave::new(192000.0, sine_wave_freq, 5.0),
-            captured_buffer: Arc::new(Mutex::new(Vec::<f32>::new())),
+            captured_buffer: Arc::new(Mutex::new(Capture::default())),
+            channels: Channels::default(),
+            export_channels: Channels::all(),
             for_tx,
-            _for_rx,
+            for_rx,
+            last_result: None,
+            distortion_settings: DistortionSettings::default(),
+            preprocess: cc
+                .storage
+                .and_then(|storage| eframe::get_value(storage, PREPROCESS_KEY))
+                .unwrap_or_default(),
+            weighting: Weighting::default(),
+            detector: None,
+            detector_block: 0.05,
+            tones: Vec::new(),
             tasker: crate::task::Tasker::new(),
             status_tx,
         }
     }
 
+    /// Saves the settings restored by `new`.
+    pub fn save(&self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, PREPROCESS_KEY, &self.preprocess);
+    }
+
     fn paint_output_sample_rate_input(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             ui.label("Output wave sample rate: ");
@@ -75,7 +132,7 @@ impl DetectTab {
             let mut val = format!("{}", self.output_sample_rate).to_string();
             ui.add(egui::TextEdit::singleline(&mut val));
             ui.label("Hz");
-            if val == "" {
+            if val.is_empty() {
                 self.output_sample_rate = 0.0;
             }
             if let Ok(parsed_val) = val.parse::<f32>() {
@@ -98,7 +155,7 @@ impl DetectTab {
             let mut val = format!("{}", self.sine_wave_freq).to_string();
             ui.add(egui::TextEdit::singleline(&mut val));
             ui.label("Hz");
-            if val == "" {
+            if val.is_empty() {
                 self.sine_wave_freq = 0.0;
             }
             if let Ok(parsed_val) = val.parse::<f32>() {
@@ -118,6 +175,8 @@ impl DetectTab {
         }
         self.started_playing = true;
         self.start_time = Instant::now();
+        self.detector = Some(self.new_detector(self.captured_sample_rate));
+        self.tones.clear();
 
         let for_tx = self.for_tx.clone();
         let captured_buffer = self.captured_buffer.clone();
@@ -137,15 +196,45 @@ impl DetectTab {
 
         // Start the wave capturing thread.
         let is_playing = self.is_playing.clone();
-        let sample_rate = self.captured_sample_rate.clone();
+        let sample_rate = self.captured_sample_rate;
+        let frequency = self.sine_wave_freq;
+        let distortion_settings = self.distortion_settings;
+        let preprocess = self.preprocess;
+        let weighting = self.weighting;
+        let channels = self.channels;
 
         spawn(move || {
             audio::capture_input(
                 input_device_name,
-                sample_rate,
                 captured_buffer,
                 for_tx,
                 is_playing,
+                |raw| {
+                    let samples =
+                        resample::resample(&raw.mix(channels), raw.sample_rate, sample_rate);
+                    let samples = preprocess.apply(&samples, sample_rate);
+                    DetectionResult {
+                        resonance: freq::freq_of_resonance(
+                            &samples,
+                            sample_rate,
+                            &Estimator {
+                                window: Window::Rectangular,
+                                interpolation: Interpolation::Quinn,
+                                ..Default::default()
+                            },
+                        ),
+                        distortion: distortion::analyze(
+                            &samples,
+                            sample_rate,
+                            frequency,
+                            &distortion_settings,
+                        ),
+                        level_db: weighting.level_db(&samples, sample_rate),
+                        weighting,
+                        stream_sample_rate: raw.sample_rate,
+                        preprocess,
+                    }
+                },
             )
         });
     }
@@ -159,7 +248,7 @@ impl DetectTab {
             let mut val = format!("{}", self.duration).to_string();
             ui.add(egui::TextEdit::singleline(&mut val));
             ui.label("Hz");
-            if val == "" {
+            if val.is_empty() {
                 self.duration = 0.0;
             }
             if let Ok(parsed_val) = val.parse::<f32>() {
@@ -248,7 +337,7 @@ impl DetectTab {
             let mut val = format!("{}", self.captured_sample_rate).to_string();
             ui.add(egui::TextEdit::singleline(&mut val));
             ui.label("Hz");
-            if val == "" {
+            if val.is_empty() {
                 self.captured_sample_rate = 0.0;
             }
             if let Ok(parsed_val) = val.parse::<f32>() {
@@ -289,6 +378,212 @@ impl DetectTab {
         });
     }
 
+    /// Returns a tone detector for the sine wave in a stream at `sample_rate`.
+    fn new_detector(&self, sample_rate: f32) -> Goertzel {
+        Goertzel::new(
+            self.sine_wave_freq,
+            sample_rate,
+            (self.detector_block * sample_rate) as usize,
+        )
+    }
+
+    /// Returns the lines recorded with the exported capture, which is saved as it was
+    /// recorded: the preprocessing of the last analysis, or the current one before the
+    /// first, and the distortion metrics the last analysis measured.
+    fn capture_metadata(&self) -> Vec<String> {
+        let preprocess = self
+            .last_result
+            .as_ref()
+            .map_or(self.preprocess, |result| result.preprocess);
+        let mut metadata = vec![format!(
+            "Preprocessing before the analysis, not applied to these samples: {}",
+            preprocess
+        )];
+        if let Some(result) = &self.last_result {
+            let (header, rows) = result.distortion.table();
+            let metrics: Vec<String> = header
+                .iter()
+                .zip(rows.iter().flatten())
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect();
+            metadata.push(format!("Distortion: {}", metrics.join(", ")));
+        }
+        metadata
+    }
+
+    /// Feeds the samples captured since the last frame, at `sample_rate`, to the tone
+    /// detector.
+    fn update_detector(&mut self, buffer: &[f32], sample_rate: f32) {
+        let Some(detector) = &self.detector else {
+            return;
+        };
+        if buffer.len() < detector.position() || detector.sample_rate() != sample_rate {
+            // The capture was cleared or restarted at another rate, start over.
+            self.detector = Some(self.new_detector(sample_rate));
+            self.tones.clear();
+        }
+        let Some(detector) = &mut self.detector else {
+            return;
+        };
+        let start = detector.position();
+        self.tones.extend(detector.process(&buffer[start..]));
+    }
+
+    fn paint_detector_block_input(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Tone detector block: ");
+            if self.is_playing.load(Ordering::SeqCst) {
+                ui.disable();
+            }
+            ui.add(
+                egui::DragValue::new(&mut self.detector_block)
+                    .speed(0.001)
+                    .range(0.001..=1.0)
+                    .suffix(" s"),
+            );
+        });
+    }
+
+    fn paint_tone_detector(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            match self.tones.last() {
+                Some(tone) => ui.label(format!(
+                    "Tone at {} Hz: {:.4} ({:.1} dBFS), phase {:.1}°",
+                    self.sine_wave_freq,
+                    tone.amplitude,
+                    tone.amplitude_db(),
+                    tone.phase.to_degrees()
+                )),
+                None => ui.label("Tone: waiting for samples"),
+            };
+            if let Some(result) = &self.last_result {
+                match result.resonance {
+                    Ok(resonance) => ui.label(format!(
+                        "Detected frequency: {:.2} ± {:.2} Hz",
+                        resonance.frequency, resonance.uncertainty
+                    )),
+                    Err(reason) => ui.colored_label(
+                        egui::Color32::DARK_RED,
+                        format!("No resonance detected: {}", reason),
+                    ),
+                };
+                if result.stream_sample_rate != self.captured_sample_rate {
+                    ui.colored_label(
+                        egui::Color32::DARK_RED,
+                        format!(
+                            "(input streamed at {} Hz, resampled to {} Hz)",
+                            result.stream_sample_rate, self.captured_sample_rate
+                        ),
+                    );
+                }
+            }
+        });
+        let amplitude: Vec<[f64; 2]> = self
+            .tones
+            .iter()
+            .map(|t| [t.time as f64, t.amplitude_db() as f64])
+            .collect();
+        let phase: Vec<[f64; 2]> = self
+            .tones
+            .iter()
+            .map(|t| [t.time as f64, t.phase.to_degrees() as f64])
+            .collect();
+        Plot::new("Tone amplitude")
+            .height(160.0)
+            .allow_scroll(false)
+            .link_axis("tone", true, false)
+            .x_axis_label("Time (s)")
+            .y_axis_label("Amplitude (dBFS)")
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(amplitude)));
+            });
+        Plot::new("Tone phase")
+            .height(120.0)
+            .allow_scroll(false)
+            .link_axis("tone", true, false)
+            .x_axis_label("Time (s)")
+            .y_axis_label("Phase (°)")
+            .include_y(-180.0)
+            .include_y(180.0)
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::new(phase)));
+            });
+    }
+
+    fn paint_max_harmonic_input(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Harmonics up to: ");
+            if self.is_playing.load(Ordering::SeqCst) {
+                ui.disable();
+            }
+            ui.add(egui::DragValue::new(&mut self.distortion_settings.max_harmonic).range(2..=20));
+        });
+    }
+
+    fn paint_weighting_input(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Weighting: ");
+            if self.is_playing.load(Ordering::SeqCst) {
+                ui.disable();
+            }
+            egui::ComboBox::new("detect_weighting", "")
+                .selected_text(self.weighting.to_string())
+                .show_ui(ui, |ui| {
+                    for weighting in [Weighting::A, Weighting::C, Weighting::Z] {
+                        ui.selectable_value(&mut self.weighting, weighting, weighting.to_string());
+                    }
+                });
+        });
+    }
+
+    fn paint_distortion(&self, ui: &mut egui::Ui) {
+        let Some(result) = &self.last_result else {
+            ui.label("Play a sine to measure its distortion");
+            return;
+        };
+        let distortion = &result.distortion;
+        egui::Grid::new("distortion")
+            .striped(true)
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Level");
+                ui.label(format!(
+                    "{:.2} dBFS, {}-weighted",
+                    result.level_db, result.weighting
+                ));
+                ui.end_row();
+                ui.label("Fundamental");
+                ui.label(format!(
+                    "{:.2} Hz, {:.2} dBFS",
+                    distortion.fundamental_frequency, distortion.fundamental_db
+                ));
+                ui.end_row();
+                for harmonic in &distortion.harmonics {
+                    ui.label(format!("H{}", harmonic.order));
+                    ui.label(format!(
+                        "{:.2} Hz, {:.1} dBc",
+                        harmonic.frequency, harmonic.level_db
+                    ));
+                    ui.end_row();
+                }
+                for (name, ratio) in [("THD", distortion.thd), ("THD+N", distortion.thd_n)] {
+                    ui.label(name);
+                    ui.label(format!(
+                        "{:.4} % ({:.1} dB)",
+                        ratio * 100.0,
+                        20.0 * ratio.max(f32::MIN_POSITIVE).log10()
+                    ));
+                    ui.end_row();
+                }
+                ui.label("SNR");
+                ui.label(format!("{:.1} dB", distortion.snr_db));
+                ui.end_row();
+                ui.label("SINAD");
+                ui.label(format!("{:.1} dB", distortion.sinad_db));
+                ui.end_row();
+            });
+    }
+
     fn paint_drain_graphs_checkbox(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             ui.checkbox(&mut self.drain_graphs, "Drain graphs");
@@ -309,7 +604,8 @@ impl DetectTab {
             }
             if ui.button("Clear").clicked() {
                 self.points_vector.clear();
-                self.captured_buffer.lock().unwrap().clear();
+                self.tones.clear();
+                *self.captured_buffer.lock().unwrap() = Capture::default();
             }
         });
     }
@@ -329,6 +625,9 @@ impl DetectTab {
                     self.paint_output_freq_input(ui);
                     self.paint_duration_input(ui);
                     self.paint_captured_input_sample_rate(ui);
+                    self.paint_detector_block_input(ui);
+                    self.paint_max_harmonic_input(ui);
+                    self.paint_weighting_input(ui);
                 },
             );
         });
@@ -340,6 +639,15 @@ impl DetectTab {
                     ui.label(egui::RichText::new("Sound controls"));
                     self.paint_sound_devices_dropdown(ui);
                     self.paint_drain_graphs_checkbox(ui);
+                    self.preprocess
+                        .paint_settings(ui, self.is_playing.load(Ordering::SeqCst));
+                    let channel_count = self.captured_buffer.lock().unwrap().channels.len();
+                    self.channels.paint_settings(
+                        ui,
+                        "Analyzed channels:",
+                        channel_count,
+                        self.is_playing.load(Ordering::SeqCst),
+                    );
                     self.paint_start_and_stop_buttons(ui);
                 },
             );
@@ -353,39 +661,87 @@ impl DetectTab {
             self.update_outgoing_wave_graph();
         }
 
-        let mut buffer_to_plot = Vec::new();
-        {
-            let captured_buffer = self.captured_buffer.lock().unwrap();
-            buffer_to_plot = captured_buffer.clone();
+        let capture = self.captured_buffer.lock().unwrap().clone();
+        let sample_rate = if capture.sample_rate > 0.0 {
+            capture.sample_rate
+        } else {
+            self.captured_sample_rate
+        };
+        // Plotted and detected as analyzed, after the preprocessing.
+        let mix = self
+            .preprocess
+            .apply(&capture.mix(self.channels), sample_rate);
+        self.update_detector(&mix, sample_rate);
+        if let Ok(result) = self.for_rx.try_recv() {
+            self.last_result = Some(result);
         }
 
-        let buf_len = buffer_to_plot.len();
+        self.spectrogram.update(&mix, sample_rate);
 
-        let mut points: Vec<[f64; 2]> = buffer_to_plot
-            .into_iter()
+        let lines: Vec<Line> = capture
+            .channels
+            .iter()
             .enumerate()
-            .map(|(i, x)| [(i as f32 / self.captured_sample_rate) as f64, x as f64])
+            .map(|(i, channel)| {
+                let mut points: Vec<[f64; 2]> = self
+                    .preprocess
+                    .apply(channel, sample_rate)
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| [(i as f32 / sample_rate) as f64, *x as f64])
+                    .collect();
+                let max_len = sample_rate as usize * 5;
+                if self.drain_graphs && points.len() > max_len {
+                    points.drain(0..points.len() - max_len);
+                }
+                Line::new(PlotPoints::new(points)).name(format!("Channel {}", i + 1))
+            })
             .collect();
-
-        if self.drain_graphs {
-            if buf_len > self.captured_sample_rate as usize * 5 {
-                points.drain(0..buf_len - self.captured_sample_rate as usize * 5);
-            }
-        }
+        ui.add_space(20.0);
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
+                ui.label(egui::RichText::new("Tone detector"));
+                self.paint_tone_detector(ui);
+            });
+        });
+        ui.add_space(20.0);
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
+                ui.label(egui::RichText::new("Distortion"));
+                self.paint_distortion(ui);
+            });
+        });
         ui.add_space(20.0);
         egui::Frame::group(ui.style()).show(ui, |ui| {
             ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
                 ui.label(egui::RichText::new("Captured Input"));
-                let line = Line::new(PlotPoints::new(points));
                 let plot = Plot::new("Received audio")
                     .allow_scroll(false)
+                    .legend(Legend::default())
                     .height(240.0);
                 plot.show(ui, |plot_ui| {
-                    plot_ui.line(line);
+                    for line in lines {
+                        plot_ui.line(line);
+                    }
                 });
+                ui.label(egui::RichText::new("Spectrogram"));
+                self.spectrogram
+                    .paint_settings(ui, self.is_playing.load(Ordering::SeqCst));
+                self.spectrogram.show(ui);
                 if self.is_playing.load(Ordering::SeqCst) {
                     ui.disable();
                 }
+                let channel_count = self
+                    .captured_buffer
+                    .lock()
+                    .map_or(1, |capture| capture.channels.len());
+                self.export_channels.paint_settings(
+                    ui,
+                    "Exported channels:",
+                    channel_count,
+                    self.is_playing.load(Ordering::SeqCst),
+                );
+                let export_channels = self.export_channels;
                 ui.horizontal(|ui| {
                     if ui.button("Export to wav").clicked {
                         if let Some(path) = rfd::FileDialog::new()
@@ -395,11 +751,16 @@ impl DetectTab {
                         {
                             let tx = self.status_tx.clone();
                             let captured_buffer = self.captured_buffer.lock().unwrap().clone();
-                            let sample_rate = self.captured_sample_rate as u32;
+                            let comment = self.capture_metadata().join("\n");
                             self.tasker.spawn(async move {
                                 tx.send("Saving wav file".to_string()).await.unwrap();
-                                audio::save_mono_vec_to_wav(&captured_buffer, sample_rate, &path)
-                                    .unwrap();
+                                audio::save_capture_to_wav(
+                                    &captured_buffer,
+                                    export_channels,
+                                    &comment,
+                                    &path,
+                                )
+                                .unwrap();
                                 tx.send("Done saving wav file".to_string()).await.unwrap();
                             });
                         }
@@ -411,13 +772,16 @@ impl DetectTab {
                             .save_file()
                         {
                             let captured_buffer = self.captured_buffer.lock().unwrap().clone();
-                            let sample_rate = self.captured_sample_rate as u32;
+                            let weighting = self.weighting;
+                            let metadata = self.capture_metadata();
                             let tx = self.status_tx.clone();
                             self.tasker.spawn(async move {
                                 tx.send("Saving csv file".to_string()).await.unwrap();
-                                audio::save_mono_vec_with_db_to_csv(
+                                audio::save_capture_with_db_to_csv(
                                     &captured_buffer,
-                                    sample_rate,
+                                    export_channels,
+                                    weighting,
+                                    &metadata,
                                     &path,
                                 )
                                 .await
@@ -426,6 +790,27 @@ impl DetectTab {
                             });
                         }
                     };
+                    if let Some(result) = &self.last_result {
+                        if ui.button("Export metrics to CSV").clicked {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .set_file_name("metrics.csv")
+                                .set_can_create_directories(true)
+                                .save_file()
+                            {
+                                let (header, rows) = result.distortion.table();
+                                let metadata =
+                                    vec![format!("Preprocessing: {}", result.preprocess)];
+                                let tx = self.status_tx.clone();
+                                self.tasker.spawn(async move {
+                                    tx.send("Saving csv file".to_string()).await.unwrap();
+                                    audio::save_columns_to_csv(&metadata, &header, &rows, &path)
+                                        .await
+                                        .unwrap();
+                                    tx.send("Done saving csv file".to_string()).await.unwrap();
+                                });
+                            }
+                        }
+                    }
                 });
                 // Request a repaint to keep the animation running
                 ctx.request_repaint();
@@ -0,0 +1,208 @@
+use crate::freq::{self, Interpolation};
+use crate::window::Window;
+
+/// Half-width in bins of the band summed around each tone. It covers the main lobe
+/// of the Blackman-Harris window used for the analysis.
+const LOBE: usize = 5;
+
+/// DistortionSettings controls the distortion analysis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DistortionSettings {
+    /// Highest harmonic included in the THD.
+    pub max_harmonic: usize,
+}
+
+impl Default for DistortionSettings {
+    fn default() -> Self {
+        Self { max_harmonic: 5 }
+    }
+}
+
+/// Harmonic is the level of one harmonic of the fundamental.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Harmonic {
+    pub order: usize,
+    pub frequency: f32,
+    /// Level relative to the fundamental, in dBc.
+    pub level_db: f32,
+}
+
+/// Distortion holds the usual audio analyzer metrics of a sine capture.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Distortion {
+    pub fundamental_frequency: f32,
+    /// Amplitude of the fundamental in dBFS.
+    pub fundamental_db: f32,
+    pub harmonics: Vec<Harmonic>,
+    /// Total harmonic distortion, as a ratio of amplitudes.
+    pub thd: f32,
+    /// Total harmonic distortion plus noise, as a ratio of amplitudes.
+    pub thd_n: f32,
+    pub snr_db: f32,
+    pub sinad_db: f32,
+}
+
+impl Distortion {
+    /// Returns the metrics as a header and a single row, for export.
+    pub fn table(&self) -> (Vec<String>, Vec<Vec<f32>>) {
+        let mut header: Vec<String> = [
+            "Fundamental (Hz)",
+            "Fundamental (dBFS)",
+            "THD (%)",
+            "THD+N (%)",
+            "SNR (dB)",
+            "SINAD (dB)",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let mut row = vec![
+            self.fundamental_frequency,
+            self.fundamental_db,
+            self.thd * 100.0,
+            self.thd_n * 100.0,
+            self.snr_db,
+            self.sinad_db,
+        ];
+        for harmonic in &self.harmonics {
+            header.push(format!("H{} (dBc)", harmonic.order));
+            row.push(harmonic.level_db);
+        }
+        (header, vec![row])
+    }
+}
+
+/// Measures the distortion of `samples`, the capture of a sine at `frequency`.
+///
+/// The spectrum is notched around the fundamental and every harmonic, and what is
+/// left is the noise, extrapolated over the notched bins. DC is left out of every
+/// metric.
+pub fn analyze(
+    samples: &[f32],
+    sample_rate: f32,
+    frequency: f32,
+    settings: &DistortionSettings,
+) -> Distortion {
+    let window = Window::BlackmanHarris;
+    let len = samples.len();
+    let bins = freq::spectrum(samples, window, 1);
+    if bins.len() <= 2 * LOBE || frequency <= 0.0 {
+        return Distortion::default();
+    }
+    // Mean square of the signal carried by each bin.
+    let sum_sq: f32 = window.coefficients(len).iter().map(|w| w * w).sum();
+    let power: Vec<f32> = bins
+        .iter()
+        .map(|c| 2.0 * c.norm_sqr() / (len as f32 * sum_sq))
+        .collect();
+    let resolution = sample_rate / len as f32;
+
+    // Sums the power of the tone nearest to `frequency`, looking for its peak a few
+    // bins around the expected one. Returns the peak bin and the tone power.
+    let tone = |frequency: f32| -> Option<(usize, f32)> {
+        let expected = (frequency / resolution).round() as usize;
+        if expected + LOBE >= power.len() {
+            return None;
+        }
+        let k = (expected.max(2 * LOBE) - LOBE..expected + LOBE)
+            .max_by(|&a, &b| power[a].total_cmp(&power[b]))?;
+        let total = power[k - LOBE..(k + LOBE + 1).min(power.len())]
+            .iter()
+            .sum();
+        Some((k, total))
+    };
+
+    let Some((peak, fundamental)) = tone(frequency) else {
+        return Distortion::default();
+    };
+    let magnitudes: Vec<f32> = power.iter().map(|p| p.sqrt()).collect();
+    let offset = freq::interpolate_peak(&[], &magnitudes, peak, Interpolation::Gaussian);
+    let fundamental_frequency = (peak as f32 + offset) * resolution;
+    let mut notched = vec![false; power.len()];
+    let mut notch = |k: usize| {
+        for n in &mut notched[k - LOBE..(k + LOBE + 1).min(power.len())] {
+            *n = true;
+        }
+    };
+    notch(peak);
+
+    let mut harmonic_power = 0.0;
+    let harmonics: Vec<Harmonic> = (2..=settings.max_harmonic)
+        .map_while(|order| {
+            let (k, level) = tone(fundamental_frequency * order as f32)?;
+            notch(k);
+            harmonic_power += level;
+            Some(Harmonic {
+                order,
+                frequency: fundamental_frequency * order as f32,
+                level_db: 10.0 * (level / fundamental).max(f32::MIN_POSITIVE).log10(),
+            })
+        })
+        .collect();
+    let (noise, count) = power[LOBE..]
+        .iter()
+        .zip(&notched[LOBE..])
+        .filter(|(_, notched)| !**notched)
+        .fold((0.0, 0), |(sum, count), (p, _)| (sum + p, count + 1));
+    let noise = (noise * (power.len() - LOBE) as f32 / count.max(1) as f32).max(f32::MIN_POSITIVE);
+    let residual = noise + harmonic_power;
+
+    Distortion {
+        fundamental_frequency,
+        fundamental_db: 10.0 * (2.0 * fundamental).max(f32::MIN_POSITIVE).log10(),
+        harmonics,
+        thd: (harmonic_power / fundamental).sqrt(),
+        thd_n: (residual / fundamental).sqrt(),
+        snr_db: 10.0 * (fundamental / noise).log10(),
+        sinad_db: 10.0 * ((fundamental + residual) / residual).log10(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::noise;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_distortion_metrics() {
+        let sample_rate = 48000.0;
+        let frequency = 1000.0;
+        let samples: Vec<f32> = noise(7, sample_rate as usize)
+            .into_iter()
+            .enumerate()
+            .map(|(i, noise)| {
+                let phase = 2.0 * PI * frequency * i as f32 / sample_rate;
+                0.5 * phase.sin()
+                    + 0.005 * (2.0 * phase).sin()
+                    + 0.0025 * (3.0 * phase).sin()
+                    + 0.001 * noise
+            })
+            .collect();
+
+        let distortion = analyze(&samples, sample_rate, frequency, &Default::default());
+        assert!((distortion.fundamental_frequency - frequency).abs() < 1.0);
+        assert!((distortion.fundamental_db + 6.02).abs() < 0.05);
+        assert_eq!(distortion.harmonics.len(), 4);
+        assert!((distortion.harmonics[0].level_db + 40.0).abs() < 0.1);
+        assert!((distortion.harmonics[1].level_db + 46.02).abs() < 0.1);
+
+        let thd = (0.005_f32.powi(2) + 0.0025_f32.powi(2)).sqrt() / 0.5;
+        assert!(
+            (distortion.thd - thd).abs() < 0.0002,
+            "Expected THD: {}, but got: {}",
+            thd,
+            distortion.thd
+        );
+        // Uniform noise of amplitude 0.001 has a mean square of 0.001² / 3.
+        let snr = 10.0 * (0.125_f32 / (0.001 * 0.001 / 3.0)).log10();
+        assert!(
+            (distortion.snr_db - snr).abs() < 1.0,
+            "Expected SNR: {}, but got: {}",
+            snr,
+            distortion.snr_db
+        );
+        assert!(distortion.thd_n > distortion.thd);
+        assert!(distortion.sinad_db < distortion.snr_db);
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+/// FftEngine plans every FFT length once and hands out the plans, which can be run
+/// from any thread. Real signals go through real-input FFTs, which take half the
+/// work and memory of complex ones.
+pub struct FftEngine {
+    real: Mutex<RealFftPlanner<f32>>,
+    complex: Mutex<FftPlanner<f32>>,
+}
+
+impl FftEngine {
+    /// Returns the engine shared by the whole program, so that a plan made for the UI
+    /// is reused by the capture thread and the other way round.
+    pub fn shared() -> &'static FftEngine {
+        static ENGINE: OnceLock<FftEngine> = OnceLock::new();
+        ENGINE.get_or_init(|| FftEngine {
+            real: Mutex::new(RealFftPlanner::new()),
+            complex: Mutex::new(FftPlanner::new()),
+        })
+    }
+
+    pub fn real_forward(&self, len: usize) -> Arc<dyn RealToComplex<f32>> {
+        self.real
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .plan_fft_forward(len)
+    }
+
+    pub fn real_inverse(&self, len: usize) -> Arc<dyn ComplexToReal<f32>> {
+        self.real
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .plan_fft_inverse(len)
+    }
+
+    pub fn forward(&self, len: usize) -> Arc<dyn Fft<f32>> {
+        self.complex
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .plan_fft_forward(len)
+    }
+
+    pub fn inverse(&self, len: usize) -> Arc<dyn Fft<f32>> {
+        self.complex
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .plan_fft_inverse(len)
+    }
+
+    /// Returns bins 0 to `input.len() / 2` of the FFT of `input`, which is used as
+    /// scratch space and left scrambled.
+    pub fn rfft(&self, input: &mut [f32]) -> Vec<Complex<f32>> {
+        if input.is_empty() {
+            return Vec::new();
+        }
+        let plan = self.real_forward(input.len());
+        let mut output = plan.make_output_vec();
+        plan.process(input, &mut output)
+            .expect("buffers are sized by the plan");
+        output
+    }
+
+    /// Returns the `len` samples of the real signal whose bins 0 to `len / 2` are
+    /// `spectrum`, unnormalized like the inverse FFT of rustfft. `spectrum` is used
+    /// as scratch space and left scrambled.
+    ///
+    /// The imaginary parts of the DC and Nyquist bins are dropped, which gives the
+    /// real part of the complex inverse of the Hermitian spectrum.
+    pub fn irfft(&self, spectrum: &mut [Complex<f32>], len: usize) -> Vec<f32> {
+        if len == 0 {
+            return Vec::new();
+        }
+        let plan = self.real_inverse(len);
+        spectrum[0].im = 0.0;
+        if len % 2 == 0 {
+            spectrum[len / 2].im = 0.0;
+        }
+        let mut output = plan.make_output_vec();
+        plan.process(spectrum, &mut output)
+            .expect("buffers are sized by the plan");
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::noise;
+
+    #[test]
+    fn test_real_fft_matches_complex_fft() {
+        let engine = FftEngine::shared();
+        for len in [1, 2, 7, 480, 1024] {
+            let samples = noise(len as u32, len);
+
+            let mut expected: Vec<Complex<f32>> =
+                samples.iter().map(|&x| Complex::new(x, 0.0)).collect();
+            engine.forward(len).process(&mut expected);
+            let mut spectrum = engine.rfft(&mut samples.clone());
+            assert_eq!(spectrum.len(), len / 2 + 1);
+            for (a, b) in spectrum.iter().zip(&expected) {
+                assert!((a - b).norm() < 1e-4, "Expected bin: {}, but got: {}", b, a);
+            }
+
+            let restored = engine.irfft(&mut spectrum, len);
+            for (a, b) in restored.iter().zip(&samples) {
+                assert!((a / len as f32 - b).abs() < 1e-5);
+            }
+        }
+
+        // Plans are made once and shared with other threads.
+        assert!(Arc::ptr_eq(
+            &engine.real_forward(1024),
+            &engine.real_forward(1024)
+        ));
+        let plan = std::thread::spawn(|| FftEngine::shared().real_forward(1024))
+            .join()
+            .unwrap();
+        assert!(Arc::ptr_eq(&plan, &engine.real_forward(1024)));
+    }
+}
@@ -0,0 +1,168 @@
+use std::f64::consts::PI;
+
+use rustfft::num_complex::Complex;
+
+/// Biquad is a second order IIR filter section, normalized so that a0 = 1.
+///
+/// It runs in double precision, as low frequencies at high sample rates put the
+/// poles and zeros very close to z = 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Biquad {
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+    pub a1: f64,
+    pub a2: f64,
+}
+
+impl Biquad {
+    /// Band-pass filter with a 0 dB peak at `frequency` and the given quality factor,
+    /// from the RBJ audio EQ cookbook.
+    pub fn band_pass(frequency: f32, q: f32, sample_rate: f32) -> Self {
+        let omega = 2.0 * PI * frequency as f64 / sample_rate as f64;
+        let alpha = omega.sin() / (2.0 * q as f64);
+        Self::normalized(
+            alpha,
+            0.0,
+            -alpha,
+            1.0 + alpha,
+            -2.0 * omega.cos(),
+            1.0 - alpha,
+        )
+    }
+
+    /// Second order high-pass filter, from the RBJ audio EQ cookbook.
+    pub fn high_pass(frequency: f32, q: f32, sample_rate: f32) -> Self {
+        let omega = 2.0 * PI * frequency as f64 / sample_rate as f64;
+        let (alpha, cos) = (omega.sin() / (2.0 * q as f64), omega.cos());
+        Self::normalized(
+            (1.0 + cos) / 2.0,
+            -(1.0 + cos),
+            (1.0 + cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    /// Second order low-pass filter, from the RBJ audio EQ cookbook.
+    pub fn low_pass(frequency: f32, q: f32, sample_rate: f32) -> Self {
+        let omega = 2.0 * PI * frequency as f64 / sample_rate as f64;
+        let (alpha, cos) = (omega.sin() / (2.0 * q as f64), omega.cos());
+        Self::normalized(
+            (1.0 - cos) / 2.0,
+            1.0 - cos,
+            (1.0 - cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    /// Notch filter rejecting `frequency`, from the RBJ audio EQ cookbook.
+    pub fn notch(frequency: f32, q: f32, sample_rate: f32) -> Self {
+        let omega = 2.0 * PI * frequency as f64 / sample_rate as f64;
+        let (alpha, cos) = (omega.sin() / (2.0 * q as f64), omega.cos());
+        Self::normalized(1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+
+    /// Digital counterpart, by the bilinear transform, of the analog section
+    /// (b0 s^2 + b1 s + b2) / (a0 s^2 + a1 s + a2), with s in radians per second.
+    pub fn bilinear(b: [f64; 3], a: [f64; 3], sample_rate: f32) -> Self {
+        let k = 2.0 * sample_rate as f64;
+        let k2 = k * k;
+        Self::normalized(
+            b[0] * k2 + b[1] * k + b[2],
+            2.0 * (b[2] - b[0] * k2),
+            b[0] * k2 - b[1] * k + b[2],
+            a[0] * k2 + a[1] * k + a[2],
+            2.0 * (a[2] - a[0] * k2),
+            a[0] * k2 - a[1] * k + a[2],
+        )
+    }
+
+    /// Returns the magnitude of the frequency response at `frequency`.
+    pub fn gain(&self, frequency: f32, sample_rate: f32) -> f64 {
+        let omega = 2.0 * PI * frequency as f64 / sample_rate as f64;
+        let z1 = Complex::from_polar(1.0, -omega);
+        let z2 = z1 * z1;
+        let numerator = z1 * self.b1 + z2 * self.b2 + self.b0;
+        let denominator = z1 * self.a1 + z2 * self.a2 + 1.0;
+        (numerator / denominator).norm()
+    }
+
+    fn normalized(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// Filters `samples`, starting from rest.
+    pub fn process(&self, samples: &[f32]) -> Vec<f32> {
+        // Transposed direct form II.
+        let (mut z1, mut z2) = (0.0, 0.0);
+        samples
+            .iter()
+            .map(|&x| {
+                let x = x as f64;
+                let y = self.b0 * x + z1;
+                z1 = self.b1 * x - self.a1 * y + z2;
+                z2 = self.b2 * x - self.a2 * y;
+                y as f32
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn level_db(frequency: f32, filter: &Biquad, sample_rate: f32) -> f32 {
+        let samples: Vec<f32> = (0..sample_rate as usize)
+            .map(|i| (2.0 * PI * frequency * i as f32 / sample_rate).sin())
+            .collect();
+        let output = filter.process(&samples);
+        // Skip the transient.
+        let peak = output[output.len() / 2..]
+            .iter()
+            .fold(0.0_f32, |m, s| m.max(s.abs()));
+        20.0 * peak.log10()
+    }
+
+    #[test]
+    fn test_band_pass_response() {
+        let sample_rate = 48000.0;
+        let filter = Biquad::band_pass(1000.0, 5.0, sample_rate);
+        assert!(level_db(1000.0, &filter, sample_rate).abs() < 0.1);
+        // The -3 dB points of a band-pass filter sit at f0 / Q apart.
+        let edge = 1000.0 * ((1.0 + 1.0 / (4.0 * 25.0_f32)).sqrt() + 0.1);
+        assert!((level_db(edge, &filter, sample_rate) + 3.01).abs() < 0.2);
+        assert!(level_db(10000.0, &filter, sample_rate) < -20.0);
+    }
+
+    #[test]
+    fn test_high_and_low_pass_response() {
+        let sample_rate = 48000.0;
+        let q = std::f32::consts::FRAC_1_SQRT_2;
+        let high_pass = Biquad::high_pass(100.0, q, sample_rate);
+        assert!((level_db(100.0, &high_pass, sample_rate) + 3.01).abs() < 0.1);
+        assert!(level_db(5000.0, &high_pass, sample_rate).abs() < 0.1);
+        // 12 dB per octave below the cutoff.
+        assert!((level_db(25.0, &high_pass, sample_rate) + 24.1).abs() < 0.5);
+
+        let low_pass = Biquad::low_pass(1000.0, q, sample_rate);
+        assert!((level_db(1000.0, &low_pass, sample_rate) + 3.01).abs() < 0.1);
+        assert!(level_db(50.0, &low_pass, sample_rate).abs() < 0.1);
+        assert!(level_db(8000.0, &low_pass, sample_rate) < -35.0);
+
+        let notch = Biquad::notch(50.0, 10.0, sample_rate);
+        assert!(level_db(50.0, &notch, sample_rate) < -40.0);
+        assert!(level_db(1000.0, &notch, sample_rate).abs() < 0.1);
+    }
+}
@@ -1,37 +1,789 @@
-use rustfft::{num_complex::Complex, FftPlanner};
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
 
-pub fn freq_of_resonance(samples: Vec<f32>, sample_rate: f32) -> f32 {
+use realfft::RealToComplex;
+use rustfft::num_complex::Complex;
+
+use crate::fft::FftEngine;
+use crate::window::Window;
+
+/// Interpolation is the method used to refine a spectral peak between FFT bins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Use the center of the peak bin.
+    None,
+    /// Fit a parabola through the magnitudes of the peak bin and its neighbours.
+    Parabolic,
+    /// Fit a parabola through the log magnitudes, exact for a Gaussian peak shape.
+    Gaussian,
+    /// Quinn's second estimator on the complex bins, only valid for the rectangular
+    /// window without zero-padding. Parabolic interpolation is used otherwise.
+    Quinn,
+}
+
+/// Estimator holds the settings used to estimate a frequency of resonance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimator {
+    pub window: Window,
+    pub interpolation: Interpolation,
+    pub zero_padding: usize,
+    /// Only peaks inside this band are considered.
+    pub band: Band,
+    pub detection: Detection,
+}
+
+impl Estimator {
+    /// Returns the interpolation actually applied to the FFT bins. Quinn's estimator
+    /// assumes the rectangular window without zero-padding, and falls back to
+    /// parabolic interpolation for any other window or padding.
+    pub fn effective_interpolation(&self) -> Interpolation {
+        match self.interpolation {
+            Interpolation::Quinn if self.window != Window::Rectangular || self.zero_padding > 1 => {
+                Interpolation::Parabolic
+            }
+            other => other,
+        }
+    }
+}
+
+impl Default for Estimator {
+    fn default() -> Self {
+        Self {
+            window: Window::default(),
+            interpolation: Interpolation::Gaussian,
+            zero_padding: 1,
+            band: Band::default(),
+            detection: Detection::default(),
+        }
+    }
+}
+
+/// Detection holds the checks the strongest peak has to pass to be reported as a
+/// resonance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    /// Percentile of the levels of all the bins of the spectrum taken as the noise
+    /// floor.
+    pub noise_percentile: f32,
+    /// Minimum height in dB of the peak above the noise floor. The strongest of
+    /// 100000 bins of white noise stands about 12 dB above their median, so this has
+    /// to be well above that.
+    pub min_snr_db: f32,
+    /// Minimum level in dBFS of the peak, below which the input is taken as silent.
+    pub min_level_db: f32,
+}
+
+impl Default for Detection {
+    fn default() -> Self {
+        Self {
+            noise_percentile: 50.0,
+            min_snr_db: 20.0,
+            min_level_db: -120.0,
+        }
+    }
+}
+
+impl Detection {
+    /// Returns the noise floor of a spectrum given as levels in dB, in the same unit.
+    pub fn noise_floor_db(&self, levels_db: &[f32]) -> f32 {
+        if levels_db.is_empty() {
+            return f32::NEG_INFINITY;
+        }
+        let mut levels = levels_db.to_vec();
+        let rank = (self.noise_percentile / 100.0).clamp(0.0, 1.0) * (levels.len() - 1) as f32;
+        let (_, floor, _) =
+            levels.select_nth_unstable_by(rank.round() as usize, |a, b| a.total_cmp(b));
+        *floor
+    }
+
+    /// Returns the height in dB of bin `peak` of `levels_db`, a spectrum in dBFS,
+    /// above the noise floor, or the reason it is not a resonance.
+    fn check(&self, levels_db: &[f32], peak: usize) -> Result<f32, NoResonance> {
+        let level_db = levels_db[peak];
+        if level_db < self.min_level_db {
+            return Err(NoResonance::Silent { level_db });
+        }
+        let snr_db = level_db - self.noise_floor_db(levels_db);
+        if snr_db < self.min_snr_db {
+            return Err(NoResonance::BelowNoise { snr_db });
+        }
+        Ok(snr_db)
+    }
+}
+
+/// NoResonance is the reason no resonance was detected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoResonance {
+    TooFewSamples,
+    /// No bin of the spectrum, or no mode of a parametric model, falls inside the
+    /// search band.
+    EmptyBand,
+    /// The strongest peak is below `Detection::min_level_db`.
+    Silent {
+        level_db: f32,
+    },
+    /// The strongest peak is less than `Detection::min_snr_db` above the noise floor.
+    BelowNoise {
+        snr_db: f32,
+    },
+}
+
+impl fmt::Display for NoResonance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoResonance::TooFewSamples => write!(f, "too few samples to analyze"),
+            NoResonance::EmptyBand => write!(f, "nothing was found in the band"),
+            NoResonance::Silent { level_db } => write!(
+                f,
+                "the input is silent, its strongest peak is at {:.1} dBFS; is the microphone muted?",
+                level_db
+            ),
+            NoResonance::BelowNoise { snr_db } => write!(
+                f,
+                "the strongest peak is only {:.1} dB above the noise floor; was the device excited?",
+                snr_db
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NoResonance {}
+
+/// Band is a range of frequencies in Hz, both ends included.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Default for Band {
+    /// The whole spectrum.
+    fn default() -> Self {
+        Self {
+            min: 0.0,
+            max: f32::INFINITY,
+        }
+    }
+}
+
+impl Band {
+    /// Returns the range of the bins of a spectrum of `len` bins spaced by
+    /// `resolution` Hz that fall inside the band.
+    pub fn bins(&self, resolution: f32, len: usize) -> Range<usize> {
+        if resolution <= 0.0 {
+            return 0..len;
+        }
+        let start = (self.min.max(0.0) / resolution).ceil() as usize;
+        let end = ((self.max / resolution).floor() as usize).saturating_add(1);
+        start.min(len)..end.min(len)
+    }
+}
+
+/// Resonance is an estimated frequency of resonance and its uncertainty, both in Hz.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Resonance {
+    pub frequency: f32,
+    pub uncertainty: f32,
+    /// Height of the peak above the noise floor in dB.
+    pub snr_db: f32,
+}
+
+/// Estimates the frequency of the strongest spectral peak of `samples`, or returns
+/// why there is none that passes `estimator.detection`.
+///
+/// The samples are windowed and zero-padded to `zero_padding` times their length
+/// before the FFT, and the peak is refined between bins with the given interpolation.
+pub fn freq_of_resonance(
+    samples: &[f32],
+    sample_rate: f32,
+    estimator: &Estimator,
+) -> Result<Resonance, NoResonance> {
+    let interpolation = estimator.effective_interpolation();
+    let Estimator {
+        window,
+        zero_padding,
+        band,
+        detection,
+        ..
+    } = *estimator;
     let num_samples = samples.len();
+    if num_samples < 3 {
+        return Err(NoResonance::TooFewSamples);
+    }
+
+    let fft_len = num_samples * zero_padding.max(1);
+    let bins = spectrum(samples, window, zero_padding);
 
-    let mut fft_input: Vec<Complex<f32>> = samples.iter().map(|&x| Complex::new(x, 0.0)).collect();
+    let magnitudes: Vec<f32> = bins.iter().map(|c| c.norm()).collect();
+
+    let freq_of_resolution = sample_rate / fft_len as f32;
+    let Some(max_index) = strongest_bin(&magnitudes, band.bins(freq_of_resolution, bins.len()))
+    else {
+        return Err(NoResonance::EmptyBand);
+    };
+    let scale = 2.0 * window.amplitude_correction(num_samples) / num_samples as f32;
+    let levels_db: Vec<f32> = magnitudes
+        .iter()
+        .map(|m| 20.0 * (m * scale).max(f32::MIN_POSITIVE).log10())
+        .collect();
+    let snr_db = detection.check(&levels_db, max_index)?;
+    let offset = interpolate_peak(&bins, &magnitudes, max_index, interpolation);
+    let uncertainty = peak_uncertainty(
+        &magnitudes,
+        max_index,
+        num_samples,
+        zero_padding,
+        window.enbw(num_samples),
+        interpolation,
+    );
 
-    let mut generic_planner = FftPlanner::new();
+    Ok(Resonance {
+        frequency: (max_index as f32 + offset) * freq_of_resolution,
+        uncertainty: uncertainty * freq_of_resolution,
+        snr_db,
+    })
+}
 
-    let fft = generic_planner.plan_fft_forward(num_samples);
+/// Zoom is the spectrum evaluated on a fine frequency grid around a peak.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Zoom {
+    pub frequencies: Vec<f32>,
+    /// Level in dBFS of a tone at each frequency.
+    pub levels_db: Vec<f32>,
+    pub resonance: Resonance,
+}
 
-    fft.process(&mut fft_input);
+/// Refines the frequency of the strongest peak of `samples` inside `band` by
+/// evaluating the windowed spectrum on `points` frequencies spread over the band.
+///
+/// The grid can be much finer than the FFT bins without zero-padding the whole
+/// capture, see `czt`.
+pub fn zoom_resonance(
+    samples: &[f32],
+    sample_rate: f32,
+    window: Window,
+    band: Band,
+    points: usize,
+) -> Zoom {
+    let num_samples = samples.len();
+    let (min, max) = (band.min.max(0.0), band.max.min(sample_rate / 2.0));
+    if num_samples < 3 || points < 3 || max <= min {
+        return Zoom::default();
+    }
+    let step = (max - min) / (points - 1) as f32;
+    let bins = czt(samples, window, sample_rate, min, step, points);
+    let magnitudes: Vec<f32> = bins.iter().map(|c| c.norm()).collect();
+    let Some(max_index) = strongest_bin(&magnitudes, 0..points) else {
+        return Zoom::default();
+    };
+    let offset = interpolate_peak(&bins, &magnitudes, max_index, Interpolation::Gaussian);
 
-    let magnitudes: Vec<f32> = fft_input[0..num_samples / 2]
+    // The grid is too narrow to show the noise floor, which is read from the plain
+    // spectrum instead.
+    let noise: Vec<f32> = spectrum(samples, window, 1)
         .iter()
         .map(|c| c.norm())
         .collect();
+    let crlb = tone_crlb(
+        &noise,
+        magnitudes[max_index],
+        num_samples,
+        window.enbw(num_samples),
+    ) * sample_rate
+        / num_samples as f32;
+    let scale = 2.0 / window.coefficients(num_samples).iter().sum::<f32>();
+    let to_db = |m: &f32| 20.0 * (m * scale).max(f32::MIN_POSITIVE).log10();
+    let levels_db: Vec<f32> = magnitudes.iter().map(to_db).collect();
+    let noise_db: Vec<f32> = noise.iter().map(to_db).collect();
 
-    let (max_index, _) = magnitudes
-        .iter()
+    Zoom {
+        frequencies: (0..points).map(|k| min + k as f32 * step).collect(),
+        resonance: Resonance {
+            frequency: min + (max_index as f32 + offset) * step,
+            uncertainty: crlb.hypot(0.05 * step),
+            snr_db: levels_db[max_index] - Detection::default().noise_floor_db(&noise_db),
+        },
+        levels_db,
+    }
+}
+
+/// Evaluates the spectrum of the windowed `samples` at the `points` frequencies
+/// `start + k * step` Hz, scaled like `spectrum`.
+///
+/// This is the chirp-z transform computed with Bluestein's algorithm: the
+/// evaluation is rewritten as a convolution with a chirp, so it costs a few FFTs of
+/// `samples.len() + points` whatever the spacing of the grid.
+pub fn czt(
+    samples: &[f32],
+    window: Window,
+    sample_rate: f32,
+    start: f32,
+    step: f32,
+    points: usize,
+) -> Vec<Complex<f32>> {
+    let len = samples.len();
+    if len == 0 || points == 0 {
+        return Vec::new();
+    }
+    let fft_len = (len + points - 1).next_power_of_two();
+    // e^{j 2 pi turns}, reduced to a single turn in f64 as the chirp phase grows
+    // with the square of the index.
+    let rotation = |turns: f64| {
+        let (sin, cos) = (2.0 * std::f64::consts::PI * turns.fract()).sin_cos();
+        Complex::new(cos as f32, sin as f32)
+    };
+    let half_step = step as f64 / sample_rate as f64 / 2.0;
+    let chirp = |n: usize| rotation(-half_step * n as f64 * n as f64);
+    let shift = start as f64 / sample_rate as f64;
+
+    let mut a: Vec<Complex<f32>> = window
+        .apply(samples)
+        .into_iter()
         .enumerate()
-        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
-        .unwrap();
+        .map(|(n, x)| rotation(-shift * n as f64) * chirp(n) * x)
+        .collect();
+    a.resize(fft_len, Complex::new(0.0, 0.0));
+    // The conjugate chirp for lags from -(len - 1) to points - 1, negative lags
+    // wrapped to the end.
+    let mut b = vec![Complex::new(0.0, 0.0); fft_len];
+    for n in 0..len.max(points) {
+        let c = chirp(n).conj();
+        if n < points {
+            b[n] = c;
+        }
+        if n > 0 && n < len {
+            b[fft_len - n] = c;
+        }
+    }
+
+    let engine = FftEngine::shared();
+    let forward = engine.forward(fft_len);
+    forward.process(&mut a);
+    forward.process(&mut b);
+    for (x, y) in a.iter_mut().zip(&b) {
+        *x *= y;
+    }
+    engine.inverse(fft_len).process(&mut a);
+    a.truncate(points);
+    a.into_iter()
+        .enumerate()
+        .map(|(k, y)| chirp(k) * y / fft_len as f32)
+        .collect()
+}
+
+/// WelchSettings controls the segment averaging of `welch`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WelchSettings {
+    pub segment_len: usize,
+    /// Fraction of overlap between consecutive segments, in [0, 1).
+    pub overlap: f32,
+    pub window: Window,
+}
+
+impl Default for WelchSettings {
+    fn default() -> Self {
+        Self {
+            segment_len: 8192,
+            overlap: 0.5,
+            window: Window::default(),
+        }
+    }
+}
+
+/// Psd is a one-sided power spectral density in units squared per Hz.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Psd {
+    pub frequencies: Vec<f32>,
+    pub power: Vec<f32>,
+    /// Length of the averaged segments.
+    pub segment_len: usize,
+    pub window: Window,
+}
+
+impl Psd {
+    pub fn power_db(&self) -> Vec<f32> {
+        self.power
+            .iter()
+            .map(|p| 10.0 * p.max(f32::MIN_POSITIVE).log10())
+            .collect()
+    }
+
+    /// Returns, for every bin, the level in dBFS of a tone whose peak sits there.
+    pub fn tone_levels_db(&self) -> Vec<f32> {
+        let enbw_hz = self.window.enbw(self.segment_len) * self.resolution();
+        self.power
+            .iter()
+            .map(|p| 10.0 * (2.0 * p * enbw_hz).max(f32::MIN_POSITIVE).log10())
+            .collect()
+    }
+
+    pub fn resolution(&self) -> f32 {
+        if self.frequencies.len() < 2 {
+            return 0.0;
+        }
+        self.frequencies[1] - self.frequencies[0]
+    }
+}
+
+/// Returns the start of every segment of `segment_len` samples, advancing by
+/// `1 - overlap` of a segment, that fits in `len` samples.
+pub fn segment_starts(len: usize, segment_len: usize, overlap: f32) -> impl Iterator<Item = usize> {
+    let step = ((segment_len as f32 * (1.0 - overlap.clamp(0.0, 0.95))) as usize).max(1);
+    (0..)
+        .map(move |i| i * step)
+        .take_while(move |start| start + segment_len <= len)
+}
+
+/// Estimates the power spectral density of `samples` with Welch's method: the
+/// periodograms of overlapping windowed segments are averaged, so memory is bounded
+/// by the segment length whatever the capture length.
+pub fn welch(samples: &[f32], sample_rate: f32, settings: &WelchSettings) -> Psd {
+    let segment_len = settings.segment_len.clamp(2, samples.len().max(2));
+    let bins = segment_len / 2;
+    let mut power = vec![0.0; bins];
+    let mut count = 0;
+    for start in segment_starts(samples.len(), segment_len, settings.overlap) {
+        let segment = spectrum(&samples[start..start + segment_len], settings.window, 1);
+        for (p, c) in power.iter_mut().zip(segment) {
+            *p += c.norm_sqr();
+        }
+        count += 1;
+    }
+
+    let sum_sq: f32 = settings
+        .window
+        .coefficients(segment_len)
+        .iter()
+        .map(|w| w * w)
+        .sum();
+    let scale = 2.0 / (sample_rate * sum_sq * count.max(1) as f32);
+    for (i, p) in power.iter_mut().enumerate() {
+        // DC has no negative frequency counterpart.
+        *p *= if i == 0 { scale / 2.0 } else { scale };
+    }
+
+    Psd {
+        frequencies: (0..bins)
+            .map(|i| i as f32 * sample_rate / segment_len as f32)
+            .collect(),
+        power,
+        segment_len,
+        window: settings.window,
+    }
+}
+
+/// Returns the index of the largest of `magnitudes` within `range`.
+fn strongest_bin(magnitudes: &[f32], range: Range<usize>) -> Option<usize> {
+    range.max_by(|&a, &b| magnitudes[a].total_cmp(&magnitudes[b]))
+}
+
+/// Estimates the frequency of the strongest peak of a power spectral density inside
+/// `band`, or returns why there is none that passes `detection`.
+///
+/// Quinn's estimator needs the complex bins, so it falls back to Gaussian interpolation.
+pub fn freq_of_resonance_in_psd(
+    psd: &Psd,
+    interpolation: Interpolation,
+    band: Band,
+    detection: &Detection,
+) -> Result<Resonance, NoResonance> {
+    if psd.power.len() < 3 {
+        return Err(NoResonance::TooFewSamples);
+    }
+    let interpolation = match interpolation {
+        Interpolation::Quinn => Interpolation::Gaussian,
+        other => other,
+    };
+    let magnitudes: Vec<f32> = psd.power.iter().map(|p| p.sqrt()).collect();
+    let Some(max_index) = strongest_bin(&magnitudes, band.bins(psd.resolution(), magnitudes.len()))
+    else {
+        return Err(NoResonance::EmptyBand);
+    };
+    let levels_db = psd.tone_levels_db();
+    let snr_db = detection.check(&levels_db, max_index)?;
+    let offset = interpolate_peak(&[], &magnitudes, max_index, interpolation);
+    let uncertainty = peak_uncertainty(
+        &magnitudes,
+        max_index,
+        psd.segment_len,
+        1,
+        psd.window.enbw(psd.segment_len),
+        interpolation,
+    );
+    Ok(Resonance {
+        frequency: (max_index as f32 + offset) * psd.resolution(),
+        uncertainty: uncertainty * psd.resolution(),
+        snr_db,
+    })
+}
+
+/// StftSettings controls the short-time Fourier transform.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StftSettings {
+    pub window: Window,
+    /// Length of each windowed frame.
+    pub frame_len: usize,
+    /// FFT size, frames shorter than it are zero-padded.
+    pub fft_len: usize,
+    /// Number of samples between the starts of consecutive frames.
+    pub hop: usize,
+}
+
+impl Default for StftSettings {
+    fn default() -> Self {
+        Self {
+            window: Window::default(),
+            frame_len: 2048,
+            fft_len: 4096,
+            hop: 1024,
+        }
+    }
+}
+
+/// Stft computes the spectra of successive frames with a shared FFT plan, so that
+/// frames can be added as samples arrive.
+pub struct Stft {
+    settings: StftSettings,
+    fft: Arc<dyn RealToComplex<f32>>,
+    coefficients: Vec<f32>,
+    scale: f32,
+}
+
+impl Stft {
+    pub fn new(settings: StftSettings) -> Self {
+        let frame_len = settings.frame_len.max(2);
+        let settings = StftSettings {
+            frame_len,
+            fft_len: settings.fft_len.max(frame_len),
+            hop: settings.hop.max(1),
+            ..settings
+        };
+        let coefficients = settings.window.coefficients(frame_len);
+        let scale = 2.0 / coefficients.iter().sum::<f32>();
+        Self {
+            fft: FftEngine::shared().real_forward(settings.fft_len),
+            settings,
+            coefficients,
+            scale,
+        }
+    }
 
-    let freq_of_resolution = sample_rate / num_samples as f32;
-    let freq_of_resonance = max_index as f32 * freq_of_resolution;
+    pub fn settings(&self) -> StftSettings {
+        self.settings
+    }
+
+    /// Returns the level in dBFS of every positive frequency bin of the frame that
+    /// starts `samples`. Missing samples are taken as zeros.
+    pub fn frame(&self, samples: &[f32]) -> Vec<f32> {
+        let mut buffer = vec![0.0; self.settings.fft_len];
+        for ((b, s), w) in buffer.iter_mut().zip(samples).zip(&self.coefficients) {
+            *b = s * w;
+        }
+        let mut bins = self.fft.make_output_vec();
+        self.fft
+            .process(&mut buffer, &mut bins)
+            .expect("buffers are sized by the plan");
+        bins.iter()
+            .take(self.settings.fft_len / 2)
+            .map(|c| 20.0 * (c.norm() * self.scale).max(f32::MIN_POSITIVE).log10())
+            .collect()
+    }
+}
+
+/// Spectrogram is the output of the short-time Fourier transform.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Spectrogram {
+    /// Center time of each frame in seconds.
+    pub times: Vec<f32>,
+    pub frequencies: Vec<f32>,
+    /// Level in dBFS, indexed by frame then frequency bin.
+    pub frames: Vec<Vec<f32>>,
+    /// Start of the next frame to analyze.
+    next_start: usize,
+}
+
+impl Spectrogram {
+    /// Appends the frames of `samples`, the whole signal so far, that were not yet
+    /// analyzed.
+    pub fn update(&mut self, stft: &Stft, samples: &[f32], sample_rate: f32) {
+        let settings = stft.settings();
+        if self.frequencies.len() != settings.fft_len / 2 {
+            self.frequencies = (0..settings.fft_len / 2)
+                .map(|k| k as f32 * sample_rate / settings.fft_len as f32)
+                .collect();
+        }
+        while self.next_start + settings.frame_len <= samples.len() {
+            let start = self.next_start;
+            self.times
+                .push((start + settings.frame_len / 2) as f32 / sample_rate);
+            self.frames
+                .push(stft.frame(&samples[start..start + settings.frame_len]));
+            self.next_start += settings.hop;
+        }
+    }
+
+    /// Drops the oldest frames so that at most `max_frames` are kept.
+    pub fn keep_last(&mut self, max_frames: usize) {
+        let excess = self.frames.len().saturating_sub(max_frames);
+        self.times.drain(..excess);
+        self.frames.drain(..excess);
+    }
+
+    /// Returns the number of samples already analyzed.
+    pub fn analyzed_len(&self) -> usize {
+        self.next_start
+    }
+}
+
+/// Computes the short-time Fourier transform of `samples`.
+pub fn stft(samples: &[f32], sample_rate: f32, settings: &StftSettings) -> Spectrogram {
+    let mut spectrogram = Spectrogram::default();
+    spectrogram.update(&Stft::new(*settings), samples, sample_rate);
+    spectrogram
+}
 
-    freq_of_resonance
+/// Returns the positive frequency half of the FFT of the windowed `samples`,
+/// zero-padded to `zero_padding` times their length.
+pub fn spectrum(samples: &[f32], window: Window, zero_padding: usize) -> Vec<Complex<f32>> {
+    let fft_len = samples.len() * zero_padding.max(1);
+
+    let mut fft_input = window.apply(samples);
+    fft_input.resize(fft_len, 0.0);
+
+    let mut bins = FftEngine::shared().rfft(&mut fft_input);
+    bins.truncate(fft_len / 2);
+    bins
+}
+
+/// Returns the full linear convolution of `a` and `b`, computed with FFTs.
+pub fn convolve(a: &[f32], b: &[f32]) -> Vec<f32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let len = a.len() + b.len() - 1;
+    let fft_len = len.next_power_of_two();
+    let engine = FftEngine::shared();
+
+    let transform = |x: &[f32]| {
+        let mut buffer = x.to_vec();
+        buffer.resize(fft_len, 0.0);
+        engine.rfft(&mut buffer)
+    };
+    let mut a = transform(a);
+    let b = transform(b);
+    for (x, y) in a.iter_mut().zip(&b) {
+        *x *= y;
+    }
+    let mut output = engine.irfft(&mut a, fft_len);
+    output.truncate(len);
+    output.into_iter().map(|x| x / fft_len as f32).collect()
+}
+
+/// Returns the offset in bins, within [-0.5, 0.5], of the true peak from bin `k`.
+///
+/// `bins` are only read by Quinn's estimator and may be empty otherwise.
+pub fn interpolate_peak(
+    bins: &[Complex<f32>],
+    magnitudes: &[f32],
+    k: usize,
+    interpolation: Interpolation,
+) -> f32 {
+    if k == 0 || k + 1 >= magnitudes.len() {
+        return 0.0;
+    }
+    let (left, center, right) = (magnitudes[k - 1], magnitudes[k], magnitudes[k + 1]);
+    let offset = match interpolation {
+        Interpolation::None => 0.0,
+        Interpolation::Parabolic => parabolic_offset(left, center, right),
+        Interpolation::Gaussian => {
+            if left <= 0.0 || right <= 0.0 {
+                parabolic_offset(left, center, right)
+            } else {
+                parabolic_offset(left.ln(), center.ln(), right.ln())
+            }
+        }
+        Interpolation::Quinn => quinn_offset(bins[k - 1], bins[k], bins[k + 1]),
+    };
+    if offset.is_finite() {
+        offset.clamp(-0.5, 0.5)
+    } else {
+        0.0
+    }
+}
+
+fn parabolic_offset(left: f32, center: f32, right: f32) -> f32 {
+    let denominator = left - 2.0 * center + right;
+    if denominator == 0.0 {
+        return 0.0;
+    }
+    0.5 * (left - right) / denominator
+}
+
+/// Quinn's second estimator, see B. G. Quinn, "Estimation of frequency, amplitude,
+/// and phase from the DFT of a time series", IEEE Trans. Signal Process., 1997.
+fn quinn_offset(left: Complex<f32>, center: Complex<f32>, right: Complex<f32>) -> f32 {
+    let tau = |x: f32| {
+        let root = (2.0_f32 / 3.0).sqrt();
+        0.25 * (3.0 * x * x + 6.0 * x + 1.0).ln()
+            - 6.0_f32.sqrt() / 24.0 * ((x + 1.0 - root) / (x + 1.0 + root)).ln()
+    };
+    let norm = center.norm_sqr();
+    if norm == 0.0 {
+        return 0.0;
+    }
+    let alpha_minus = (left * center.conj()).re / norm;
+    let alpha_plus = (right * center.conj()).re / norm;
+    let delta_minus = alpha_minus / (1.0 - alpha_minus);
+    let delta_plus = -alpha_plus / (1.0 - alpha_plus);
+    (delta_plus + delta_minus) / 2.0 + tau(delta_plus * delta_plus) - tau(delta_minus * delta_minus)
+}
+
+/// Returns the standard uncertainty of the peak position in bins.
+///
+/// It combines the Cramer-Rao bound for a single tone in white noise, with the noise
+/// level taken from the median bin, and the worst-case bias of the interpolation.
+fn peak_uncertainty(
+    magnitudes: &[f32],
+    k: usize,
+    num_samples: usize,
+    zero_padding: usize,
+    enbw: f32,
+    interpolation: Interpolation,
+) -> f32 {
+    let padding = zero_padding.max(1) as f32;
+    let bias = match interpolation {
+        Interpolation::None => 0.5,
+        Interpolation::Parabolic => 0.1 / padding,
+        Interpolation::Gaussian => 0.05 / padding,
+        Interpolation::Quinn => 0.01,
+    };
+    let crlb = tone_crlb(magnitudes, magnitudes[k], num_samples, enbw);
+    (crlb * padding).hypot(bias)
+}
+
+/// Returns the Cramer-Rao bound, in bins of the unpadded FFT, on the frequency of a
+/// tone whose bin magnitude is `peak`, or zero without noise. The noise level is the
+/// median of `magnitudes`.
+fn tone_crlb(magnitudes: &[f32], peak: f32, num_samples: usize, enbw: f32) -> f32 {
+    let mut powers: Vec<f32> = magnitudes.iter().map(|m| m * m).collect();
+    let peak_power = peak * peak;
+    let mid = powers.len() / 2;
+    let (_, noise_power, _) = powers.select_nth_unstable_by(mid, |a, b| a.total_cmp(b));
+    let noise_power = *noise_power;
+    if noise_power <= 0.0 || peak_power <= 0.0 {
+        return 0.0;
+    }
+    // Per-sample SNR from the per-bin SNR of a windowed tone.
+    let n = num_samples as f32;
+    let snr = 2.0 * enbw * peak_power / (noise_power * n);
+    (12.0 / (snr * n * (n * n - 1.0))).sqrt() * n / (2.0 * std::f32::consts::PI)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use hound;
+    use crate::test_util::noise;
 
     use rustfft::{num_complex::Complex, FftPlanner};
     use std::f32::consts::PI;
@@ -81,10 +833,21 @@ mod tests {
         println!("Duration: {}", reader.duration());
         let samples: Vec<f32> = reader
             .samples::<f32>() // Assume the WAV file has 16-bit samples
-            .map(|s| s.unwrap() as f32 / f32::MAX as f32) // Convert samples to f32 in range -1.0 to 1.0
+            .map(|s| s.unwrap() / f32::MAX) // Convert samples to f32 in range -1.0 to 1.0
             .collect();
 
-        let res = freq_of_resonance(samples.clone(), 192000.00);
+        let res = freq_of_resonance(
+            &samples,
+            192000.00,
+            &Estimator {
+                window: Window::Rectangular,
+                interpolation: Interpolation::Quinn,
+                zero_padding: 1,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .frequency;
         if (res - 1348.00).abs() > 1.0 {
             println!("Expected freq of resonance = 1348, but got {}", res);
         }
@@ -98,7 +861,18 @@ mod tests {
         let duration = 1.0; // 1 second
 
         let samples = generate_sine_wave(frequency, sample_rate, duration);
-        let calculated_frequency = freq_of_resonance(samples, sample_rate);
+        let calculated_frequency = freq_of_resonance(
+            &samples,
+            sample_rate,
+            &Estimator {
+                window: Window::Rectangular,
+                interpolation: Interpolation::Quinn,
+                zero_padding: 1,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .frequency;
         // Assert that the calculated frequency is close to 440 Hz
         assert!(
             (calculated_frequency - frequency).abs() < 1.0,
@@ -108,6 +882,336 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sine_wave_off_bin_sub_hz_accuracy() {
+        let sample_rate = 44100.0;
+        let duration = 0.25; // 4 Hz bins
+        for frequency in [440.7, 1001.3, 1348.5, 5012.9] {
+            let samples = generate_sine_wave(frequency, sample_rate, duration);
+            for (window, interpolation, zero_padding) in [
+                (Window::Rectangular, Interpolation::Quinn, 1),
+                (Window::Rectangular, Interpolation::Gaussian, 4),
+                (Window::BlackmanHarris, Interpolation::Gaussian, 1),
+                (Window::Hann, Interpolation::Parabolic, 4),
+                (Window::Hann, Interpolation::Gaussian, 1),
+            ] {
+                let res = freq_of_resonance(
+                    &samples,
+                    sample_rate,
+                    &Estimator {
+                        window,
+                        interpolation,
+                        zero_padding,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+                assert!(
+                    (res.frequency - frequency).abs() < 0.5,
+                    "{} {:?}: expected frequency: {}, but got: {}",
+                    window,
+                    interpolation,
+                    frequency,
+                    res.frequency
+                );
+                assert!(
+                    (res.frequency - frequency).abs() <= res.uncertainty * 3.0,
+                    "{} {:?}: error {} is not covered by uncertainty {}",
+                    window,
+                    interpolation,
+                    (res.frequency - frequency).abs(),
+                    res.uncertainty
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_zero_padding_improves_parabolic_estimate() {
+        let sample_rate = 44100.0;
+        let frequency = 441.9;
+        let samples = generate_sine_wave(frequency, sample_rate, 0.25);
+
+        let coarse = freq_of_resonance(
+            &samples,
+            sample_rate,
+            &Estimator {
+                window: Window::Rectangular,
+                interpolation: Interpolation::None,
+                zero_padding: 1,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let padded = freq_of_resonance(
+            &samples,
+            sample_rate,
+            &Estimator {
+                window: Window::Rectangular,
+                interpolation: Interpolation::Parabolic,
+                zero_padding: 8,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!((coarse.frequency - frequency).abs() > 1.0);
+        assert!(
+            (padded.frequency - frequency).abs() < 0.1,
+            "Expected frequency: {}, but got: {}",
+            frequency,
+            padded.frequency
+        );
+        assert!(padded.uncertainty < coarse.uncertainty);
+    }
+
+    #[test]
+    fn test_quinn_falls_back_to_parabolic() {
+        let sample_rate = 44100.0;
+        let samples = generate_sine_wave(441.9, sample_rate, 0.25);
+        for (window, zero_padding) in [(Window::Hann, 1), (Window::Rectangular, 4)] {
+            let estimate = |interpolation| {
+                freq_of_resonance(
+                    &samples,
+                    sample_rate,
+                    &Estimator {
+                        window,
+                        interpolation,
+                        zero_padding,
+                        ..Default::default()
+                    },
+                )
+                .unwrap()
+            };
+            assert_eq!(
+                estimate(Interpolation::Quinn),
+                estimate(Interpolation::Parabolic)
+            );
+        }
+    }
+
+    #[test]
+    fn test_welch_psd_levels() {
+        let sample_rate = 48000.0;
+        let settings = WelchSettings {
+            segment_len: 4096,
+            ..Default::default()
+        };
+
+        // Uniform noise in [-1, 1] has a variance of 1/3.
+        let noise = noise(1, sample_rate as usize * 4);
+        let psd = welch(&noise, sample_rate, &settings);
+        let mean = psd.power[1..].iter().sum::<f32>() / (psd.power.len() - 1) as f32;
+        let expected = 2.0 / 3.0 / sample_rate;
+        assert!(
+            (mean / expected - 1.0).abs() < 0.05,
+            "Expected density: {}, but got: {}",
+            expected,
+            mean
+        );
+
+        // A bin-centered tone of amplitude 0.5 reads as -6 dBFS.
+        let frequency = 1500.0 * sample_rate / 48000.0;
+        let tone = generate_sine_wave(frequency, sample_rate, 4.0)
+            .iter()
+            .map(|s| s * 0.5)
+            .collect::<Vec<f32>>();
+        let psd = welch(&tone, sample_rate, &settings);
+        let resonance = freq_of_resonance_in_psd(
+            &psd,
+            Interpolation::Gaussian,
+            Band::default(),
+            &Detection::default(),
+        )
+        .unwrap();
+        assert!((resonance.frequency - frequency).abs() < 1.0);
+        let peak = psd.tone_levels_db().into_iter().fold(f32::MIN, f32::max);
+        assert!((peak + 6.02).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_band_limited_search_ignores_hum() {
+        let sample_rate = 48000.0;
+        let hum = generate_sine_wave(50.0, sample_rate, 1.0);
+        let samples: Vec<f32> = generate_sine_wave(1234.0, sample_rate, 1.0)
+            .iter()
+            .zip(&hum)
+            .map(|(s, h)| 0.1 * s + 0.8 * h + 0.3)
+            .collect();
+        let band = Band {
+            min: 200.0,
+            max: 5000.0,
+        };
+
+        let unbounded = freq_of_resonance(&samples, sample_rate, &Estimator::default()).unwrap();
+        assert!(unbounded.frequency < 200.0);
+        let estimator = Estimator {
+            band,
+            ..Default::default()
+        };
+        let resonance = freq_of_resonance(&samples, sample_rate, &estimator).unwrap();
+        assert!(
+            (resonance.frequency - 1234.0).abs() < 0.1,
+            "Expected frequency: 1234, but got: {}",
+            resonance.frequency
+        );
+
+        let psd = welch(&samples, sample_rate, &WelchSettings::default());
+        let resonance =
+            freq_of_resonance_in_psd(&psd, Interpolation::Gaussian, band, &Detection::default())
+                .unwrap();
+        assert!((resonance.frequency - 1234.0).abs() < 1.0);
+
+        let empty = Band {
+            min: 30000.0,
+            max: 40000.0,
+        };
+        assert_eq!(empty.bins(1.0, 24000), 24000..24000);
+        let estimator = Estimator {
+            band: empty,
+            ..Default::default()
+        };
+        assert_eq!(
+            freq_of_resonance(&samples, sample_rate, &estimator),
+            Err(NoResonance::EmptyBand)
+        );
+    }
+
+    #[test]
+    fn test_no_resonance_in_silence_or_noise() {
+        let sample_rate = 48000.0;
+        let estimator = Estimator::default();
+        assert!(matches!(
+            freq_of_resonance(&vec![0.0; 48000], sample_rate, &estimator),
+            Err(NoResonance::Silent { .. })
+        ));
+
+        let noise: Vec<f32> = noise(3, sample_rate as usize)
+            .into_iter()
+            .map(|n| 0.01 * n)
+            .collect();
+        let result = freq_of_resonance(&noise, sample_rate, &estimator);
+        assert!(
+            matches!(result, Err(NoResonance::BelowNoise { snr_db }) if snr_db > 5.0),
+            "Expected noise to be rejected, but got: {:?}",
+            result
+        );
+        let psd = welch(&noise, sample_rate, &WelchSettings::default());
+        assert!(matches!(
+            freq_of_resonance_in_psd(
+                &psd,
+                Interpolation::Gaussian,
+                Band::default(),
+                &estimator.detection
+            ),
+            Err(NoResonance::BelowNoise { .. })
+        ));
+
+        let samples: Vec<f32> = generate_sine_wave(1000.0, sample_rate, 1.0)
+            .iter()
+            .zip(&noise)
+            .map(|(s, n)| 0.01 * s + n)
+            .collect();
+        let resonance = freq_of_resonance(&samples, sample_rate, &estimator).unwrap();
+        assert!((resonance.frequency - 1000.0).abs() < 0.1);
+        assert!(
+            resonance.snr_db > 30.0,
+            "Expected SNR above 30 dB, but got: {}",
+            resonance.snr_db
+        );
+    }
+
+    #[test]
+    fn test_stft_follows_frequency_steps() {
+        let sample_rate = 48000.0;
+        let mut samples = generate_sine_wave(1000.0, sample_rate, 0.5);
+        samples.extend(generate_sine_wave(3000.0, sample_rate, 0.5));
+        let settings = StftSettings {
+            frame_len: 1024,
+            fft_len: 4096,
+            hop: 512,
+            ..Default::default()
+        };
+        let spectrogram = stft(&samples, sample_rate, &settings);
+        assert_eq!(spectrogram.frames.len(), (48000 - 1024) / 512 + 1);
+        assert_eq!(spectrogram.frequencies.len(), 2048);
+        for (time, frame) in spectrogram.times.iter().zip(&spectrogram.frames) {
+            if (time - 0.5).abs() < 0.05 {
+                continue;
+            }
+            let (k, level) = frame
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .unwrap();
+            let expected = if *time < 0.5 { 1000.0 } else { 3000.0 };
+            assert!((spectrogram.frequencies[k] - expected).abs() < 12.0);
+            assert!(level.abs() < 1.5, "Expected 0 dBFS, but got: {}", level);
+        }
+    }
+
+    #[test]
+    fn test_czt_matches_dft() {
+        let sample_rate = 8000.0;
+        let mut samples = generate_sine_wave(1234.5, sample_rate, 0.125);
+        for (i, s) in samples.iter_mut().enumerate() {
+            *s += 0.3 * (2.0 * PI * 2500.0 * i as f32 / sample_rate).cos();
+        }
+        let (start, step) = (1200.0, 0.37);
+        let bins = czt(&samples, Window::Hann, sample_rate, start, step, 200);
+        let windowed = Window::Hann.apply(&samples);
+        for (k, bin) in bins.iter().enumerate() {
+            let omega =
+                2.0 * std::f64::consts::PI * (start + k as f32 * step) as f64 / sample_rate as f64;
+            let (re, im) = windowed
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(re, im), (n, x)| {
+                    let phase = omega * n as f64;
+                    (re + *x as f64 * phase.cos(), im - *x as f64 * phase.sin())
+                });
+            let expected = Complex::new(re as f32, im as f32);
+            // Single precision round-off grows with the number of samples summed.
+            assert!(
+                (bin - expected).norm() < 1e-5 * samples.len() as f32,
+                "Expected bin: {}, but got: {}",
+                expected,
+                bin
+            );
+        }
+    }
+
+    #[test]
+    fn test_zoom_resonance_refines_between_bins() {
+        // 4 Hz bins, with the tone between two of them.
+        let sample_rate = 48000.0;
+        let frequency = 1001.37;
+        let tone = generate_sine_wave(frequency, sample_rate, 0.25);
+        let samples: Vec<f32> = tone
+            .iter()
+            .zip(noise(3, tone.len()))
+            .map(|(s, n)| 0.5 * s + 0.01 * n)
+            .collect();
+        let band = Band {
+            min: 980.0,
+            max: 1020.0,
+        };
+        let zoom = zoom_resonance(&samples, sample_rate, Window::Hann, band, 2001);
+        assert_eq!(zoom.frequencies.len(), 2001);
+        assert!(
+            (zoom.resonance.frequency - frequency).abs() < 0.01,
+            "Expected frequency: {}, but got: {}",
+            frequency,
+            zoom.resonance.frequency
+        );
+        assert!(zoom.resonance.uncertainty < 0.01);
+        let peak = zoom.levels_db.iter().cloned().fold(f32::MIN, f32::max);
+        assert!(
+            (peak + 6.02).abs() < 0.1,
+            "Expected -6.02 dBFS, but got: {}",
+            peak
+        );
+    }
+
     #[test]
     fn test_fft_symmetry() {
         let sample_rate = 44100.0;
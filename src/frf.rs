@@ -0,0 +1,213 @@
+use rustfft::num_complex::Complex;
+
+use crate::freq;
+use crate::window::Window;
+
+/// FrfEstimator is the way the cross spectra are combined into H(f).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrfEstimator {
+    /// H1 = Sxy / Sxx, unbiased by noise on the response.
+    H1,
+    /// H2 = Syy / Syx, unbiased by noise on the excitation.
+    H2,
+}
+
+/// FrfSettings controls the cross-spectral averaging of `estimate_frf`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrfSettings {
+    pub estimator: FrfEstimator,
+    /// Length of each averaged segment. A length covering the whole signal turns
+    /// the estimate into a plain FFT division.
+    pub segment_len: usize,
+    /// Fraction of overlap between consecutive segments, in [0, 1).
+    pub overlap: f32,
+    pub window: Window,
+}
+
+impl Default for FrfSettings {
+    fn default() -> Self {
+        Self {
+            estimator: FrfEstimator::H1,
+            segment_len: 16384,
+            overlap: 0.5,
+            window: Window::default(),
+        }
+    }
+}
+
+/// Frf is a frequency response function sampled at `frequencies` in Hz.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Frf {
+    pub frequencies: Vec<f32>,
+    pub response: Vec<Complex<f32>>,
+    /// Magnitude-squared coherence between excitation and response, in [0, 1]. It
+    /// is only meaningful when more than one segment was averaged.
+    pub coherence: Vec<f32>,
+}
+
+impl Frf {
+    pub fn magnitude_db(&self) -> Vec<f32> {
+        self.response
+            .iter()
+            .map(|h| 20.0 * h.norm().max(f32::MIN_POSITIVE).log10())
+            .collect()
+    }
+
+    pub fn phase_deg(&self) -> Vec<f32> {
+        self.response.iter().map(|h| h.arg().to_degrees()).collect()
+    }
+
+    /// Returns the coherence at `frequency`, linearly interpolated between bins.
+    pub fn coherence_at(&self, frequency: f32) -> f32 {
+        if self.coherence.len() < 2 {
+            return self.coherence.first().copied().unwrap_or(0.0);
+        }
+        let resolution = self.frequencies[1] - self.frequencies[0];
+        let position = (frequency / resolution).clamp(0.0, (self.coherence.len() - 1) as f32);
+        let i = (position as usize).min(self.coherence.len() - 2);
+        let t = position - i as f32;
+        self.coherence[i] * (1.0 - t) + self.coherence[i + 1] * t
+    }
+}
+
+/// CrossSpectra holds the averaged auto and cross spectra of an excitation x and a
+/// response y.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CrossSpectra {
+    pub sxx: Vec<f32>,
+    pub syy: Vec<f32>,
+    pub sxy: Vec<Complex<f32>>,
+}
+
+/// Averages the auto and cross spectra of `excitation` and `response` over windowed
+/// segments. Both signals are truncated to the shorter one.
+pub fn cross_spectra(excitation: &[f32], response: &[f32], settings: &FrfSettings) -> CrossSpectra {
+    let len = excitation.len().min(response.len());
+    let segment_len = settings.segment_len.clamp(2, len.max(2));
+    let bins = segment_len / 2;
+    let mut spectra = CrossSpectra {
+        sxx: vec![0.0; bins],
+        syy: vec![0.0; bins],
+        sxy: vec![Complex::new(0.0, 0.0); bins],
+    };
+    if len < 2 {
+        return spectra;
+    }
+
+    for start in freq::segment_starts(len, segment_len, settings.overlap) {
+        let x = freq::spectrum(&excitation[start..start + segment_len], settings.window, 1);
+        let y = freq::spectrum(&response[start..start + segment_len], settings.window, 1);
+        for i in 0..bins {
+            spectra.sxx[i] += x[i].norm_sqr();
+            spectra.syy[i] += y[i].norm_sqr();
+            spectra.sxy[i] += x[i].conj() * y[i];
+        }
+    }
+    spectra
+}
+
+/// Estimates the frequency response function from `excitation` to `response`.
+pub fn estimate_frf(
+    excitation: &[f32],
+    response: &[f32],
+    sample_rate: f32,
+    settings: &FrfSettings,
+) -> Frf {
+    let spectra = cross_spectra(excitation, response, settings);
+    let bins = spectra.sxx.len();
+    let freq_of_resolution = sample_rate / (bins * 2) as f32;
+    let response = (0..bins)
+        .map(|i| match settings.estimator {
+            FrfEstimator::H1 => spectra.sxy[i] / spectra.sxx[i].max(f32::MIN_POSITIVE),
+            FrfEstimator::H2 => {
+                let syx = spectra.sxy[i].conj();
+                if syx.norm_sqr() == 0.0 {
+                    Complex::new(0.0, 0.0)
+                } else {
+                    Complex::new(spectra.syy[i], 0.0) / syx
+                }
+            }
+        })
+        .collect();
+    let coherence = (0..bins)
+        .map(|i| {
+            let power = spectra.sxx[i] * spectra.syy[i];
+            if power > 0.0 {
+                (spectra.sxy[i].norm_sqr() / power).min(1.0)
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    Frf {
+        frequencies: (0..bins).map(|i| i as f32 * freq_of_resolution).collect(),
+        response,
+        coherence,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::noise;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_frf_of_fir_filter() {
+        let sample_rate = 48000.0;
+        let x = noise(12345, 48000);
+        // y[n] = x[n] + 0.5 x[n - 1]
+        let y: Vec<f32> = (0..x.len())
+            .map(|n| x[n] + if n > 0 { 0.5 * x[n - 1] } else { 0.0 })
+            .collect();
+        for estimator in [FrfEstimator::H1, FrfEstimator::H2] {
+            let frf = estimate_frf(
+                &x,
+                &y,
+                sample_rate,
+                &FrfSettings {
+                    estimator,
+                    segment_len: 1024,
+                    ..Default::default()
+                },
+            );
+            for i in [10, 100, 256, 400] {
+                let w = 2.0 * PI * frf.frequencies[i] / sample_rate;
+                let expected = Complex::new(1.0, 0.0) + Complex::from_polar(0.5, -w);
+                assert!(
+                    (frf.response[i] - expected).norm() < 0.05,
+                    "{:?} bin {}: expected {}, but got {}",
+                    estimator,
+                    i,
+                    expected,
+                    frf.response[i]
+                );
+                assert!(frf.coherence[i] > 0.99);
+            }
+        }
+    }
+
+    #[test]
+    fn test_coherence_drops_with_uncorrelated_noise() {
+        let x = noise(12345, 96000);
+        let noise = noise(777, 96000);
+        // Equal excitation and noise power gives a coherence of 1/2.
+        let y: Vec<f32> = x.iter().zip(&noise).map(|(a, b)| a + b).collect();
+        let frf = estimate_frf(
+            &x,
+            &y,
+            48000.0,
+            &FrfSettings {
+                segment_len: 1024,
+                ..Default::default()
+            },
+        );
+        let mean = frf.coherence[1..].iter().sum::<f32>() / (frf.coherence.len() - 1) as f32;
+        assert!(
+            (mean - 0.5).abs() < 0.05,
+            "Expected coherence: 0.5, but got: {}",
+            mean
+        );
+        assert!((frf.coherence_at(frf.frequencies[100]) - frf.coherence[100]).abs() < 1e-6);
+    }
+}
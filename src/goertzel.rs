@@ -0,0 +1,160 @@
+use std::f32::consts::PI;
+
+use crate::window::Window;
+
+/// Tone is the amplitude and phase of the detected frequency over one block.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Tone {
+    /// Time in seconds of the end of the block, from the start of the stream.
+    pub time: f32,
+    pub amplitude: f32,
+    /// Phase in radians of the equivalent cosine, relative to the start of the
+    /// stream, so that it stays constant for a steady tone.
+    pub phase: f32,
+}
+
+impl Tone {
+    pub fn amplitude_db(&self) -> f32 {
+        20.0 * self.amplitude.max(f32::MIN_POSITIVE).log10()
+    }
+}
+
+/// Goertzel is a streaming single frequency DFT. Samples can be fed in chunks of any
+/// size, and every completed block yields a `Tone`.
+///
+/// The frequency does not have to fall on a DFT bin, and the block is Hann windowed
+/// to keep other components from leaking in.
+#[derive(Debug, Clone)]
+pub struct Goertzel {
+    omega: f32,
+    coefficient: f32,
+    sample_rate: f32,
+    window: Vec<f32>,
+    gain: f32,
+    s1: f32,
+    s2: f32,
+    /// Position in the current block.
+    index: usize,
+    /// Stream position of the start of the current block.
+    block_start: usize,
+}
+
+impl Goertzel {
+    pub fn new(frequency: f32, sample_rate: f32, block_len: usize) -> Self {
+        let omega = 2.0 * PI * frequency / sample_rate;
+        let window = Window::Hann.coefficients(block_len.max(2));
+        let gain = window.iter().sum::<f32>() / 2.0;
+        Self {
+            omega,
+            coefficient: 2.0 * omega.cos(),
+            sample_rate,
+            window,
+            gain,
+            s1: 0.0,
+            s2: 0.0,
+            index: 0,
+            block_start: 0,
+        }
+    }
+
+    /// Returns the sample rate the detector was built for.
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Returns the number of samples fed so far.
+    pub fn position(&self) -> usize {
+        self.block_start + self.index
+    }
+
+    /// Feeds the next `samples` of the stream and returns the tones of the blocks
+    /// they complete.
+    pub fn process(&mut self, samples: &[f32]) -> Vec<Tone> {
+        let mut tones = Vec::new();
+        for &x in samples {
+            let s = x * self.window[self.index] + self.coefficient * self.s1 - self.s2;
+            self.s2 = self.s1;
+            self.s1 = s;
+            self.index += 1;
+            if self.index == self.window.len() {
+                tones.push(self.finish_block());
+            }
+        }
+        tones
+    }
+
+    fn finish_block(&mut self) -> Tone {
+        let len = self.window.len();
+        // y = s[N-1] - e^{-jw} s[N-2], and X(w) = e^{-jw(N-1)} y.
+        let re = self.s1 - self.s2 * self.omega.cos();
+        let im = self.s2 * self.omega.sin();
+        let rotation = -self.omega * (len - 1) as f32;
+        let (sin, cos) = rotation.sin_cos();
+        let (re, im) = (re * cos - im * sin, re * sin + im * cos);
+
+        // Remove the advance of the tone since the start of the stream, in f64 as the
+        // stream position grows without bound.
+        let advance = (self.omega as f64 * self.block_start as f64) % (2.0 * std::f64::consts::PI);
+        let phase = wrap_phase(im.atan2(re) - advance as f32);
+
+        self.block_start += len;
+        self.index = 0;
+        self.s1 = 0.0;
+        self.s2 = 0.0;
+        Tone {
+            time: self.block_start as f32 / self.sample_rate,
+            amplitude: (re * re + im * im).sqrt() / self.gain,
+            phase,
+        }
+    }
+}
+
+/// Wraps a phase in radians to (-pi, pi].
+fn wrap_phase(phase: f32) -> f32 {
+    let wrapped = (phase + PI).rem_euclid(2.0 * PI) - PI;
+    if wrapped == -PI {
+        PI
+    } else {
+        wrapped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_goertzel_amplitude_and_phase() {
+        let sample_rate = 48000.0;
+        let (frequency, amplitude, phase) = (1234.5_f32, 0.3_f32, 40.0_f32.to_radians());
+        let samples: Vec<f32> = (0..sample_rate as usize)
+            .map(|i| {
+                let t = i as f32 / sample_rate;
+                amplitude * (2.0 * PI * frequency * t + phase).cos()
+                    + 0.2 * (2.0 * PI * 3000.0 * t).sin()
+            })
+            .collect();
+
+        let mut detector = Goertzel::new(frequency, sample_rate, 2400);
+        let tones = detector.process(&samples);
+        assert_eq!(tones.len(), 20);
+        for tone in &tones {
+            assert!(
+                (tone.amplitude - amplitude).abs() < 0.003,
+                "Expected amplitude: {}, but got: {}",
+                amplitude,
+                tone.amplitude
+            );
+            assert!((tone.phase - phase).abs() < 0.02);
+        }
+
+        // Feeding the stream in uneven chunks gives the same tones.
+        let mut detector = Goertzel::new(frequency, sample_rate, 2400);
+        let mut chunked = Vec::new();
+        for chunk in samples.chunks(1000) {
+            chunked.extend(detector.process(chunk));
+        }
+        assert_eq!(chunked, tones);
+        assert_eq!(detector.position(), samples.len());
+    }
+}
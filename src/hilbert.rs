@@ -0,0 +1,161 @@
+use rustfft::num_complex::Complex;
+
+use crate::fft::FftEngine;
+use crate::freq::Band;
+
+/// Instantaneous is the envelope and instantaneous frequency of a signal, read from
+/// its analytic signal sample by sample.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Instantaneous {
+    pub sample_rate: f32,
+    /// Amplitude of the analytic signal, 1 for a full scale sine.
+    pub envelope: Vec<f32>,
+    /// Derivative of the phase of the analytic signal, in Hz.
+    pub frequency: Vec<f32>,
+}
+
+/// Returns the analytic signal of `samples`, whose real part is `samples` and whose
+/// imaginary part is their Hilbert transform.
+///
+/// The negative frequencies of the spectrum are zeroed and the positive ones
+/// doubled. The transform is circular, so the ends of a signal that does not start
+/// and stop at rest ripple.
+pub fn analytic_signal(samples: &[f32]) -> Vec<Complex<f32>> {
+    let len = samples.len();
+    if len == 0 {
+        return Vec::new();
+    }
+    let engine = FftEngine::shared();
+    // The real FFT gives the positive half, the negative one is left at zero.
+    let mut buffer = engine.rfft(&mut samples.to_vec());
+    // DC, and Nyquist for an even length, are shared by both halves and kept as is.
+    for (k, bin) in buffer.iter_mut().enumerate().skip(1) {
+        if 2 * k < len {
+            *bin *= 2.0;
+        }
+    }
+    buffer.resize(len, Complex::new(0.0, 0.0));
+    engine.inverse(len).process(&mut buffer);
+    buffer.iter().map(|c| c / len as f32).collect()
+}
+
+/// Returns the envelope and instantaneous frequency of `samples`.
+///
+/// The frequency is the mean phase step to and from the neighbouring samples, which
+/// holds up to Nyquist. It is only meaningful where a single component dominates.
+pub fn instantaneous(samples: &[f32], sample_rate: f32) -> Instantaneous {
+    let analytic = analytic_signal(samples);
+    let len = analytic.len();
+    let frequency = (0..len)
+        .map(|i| {
+            let (before, after) = (i.saturating_sub(1), (i + 1).min(len - 1));
+            if before == after {
+                return 0.0;
+            }
+            let step = (analytic[after] * analytic[i].conj()).arg()
+                + (analytic[i] * analytic[before].conj()).arg();
+            step / (after - before) as f32 * sample_rate / (2.0 * std::f32::consts::PI)
+        })
+        .collect();
+    Instantaneous {
+        sample_rate,
+        envelope: analytic.iter().map(|c| c.norm()).collect(),
+        frequency,
+    }
+}
+
+impl Instantaneous {
+    /// Returns the envelope in dBFS against the instantaneous frequency, averaged in
+    /// `bins` equal bins over `band`, as a quick response curve for a swept
+    /// excitation. Samples more than 60 dB below the peak of the envelope are left
+    /// out, as their frequency is noise, and so are empty bins.
+    pub fn response_curve(&self, band: Band, bins: usize) -> Vec<[f32; 2]> {
+        let width = (band.max - band.min) / bins.max(1) as f32;
+        if width <= 0.0 {
+            return Vec::new();
+        }
+        let threshold = self.envelope.iter().fold(0.0_f32, |m, e| m.max(*e)) * 1e-3;
+        let mut sums = vec![(0.0_f64, 0_usize); bins.max(1)];
+        for (envelope, frequency) in self.envelope.iter().zip(&self.frequency) {
+            if *envelope <= threshold || *frequency < band.min || *frequency >= band.max {
+                continue;
+            }
+            let bin = (((frequency - band.min) / width) as usize).min(sums.len() - 1);
+            sums[bin].0 += *envelope as f64;
+            sums[bin].1 += 1;
+        }
+        sums.iter()
+            .enumerate()
+            .filter(|(_, (_, count))| *count > 0)
+            .map(|(i, (sum, count))| {
+                let level = (*sum / *count as f64) as f32;
+                [
+                    band.min + (i as f32 + 0.5) * width,
+                    20.0 * level.max(f32::MIN_POSITIVE).log10(),
+                ]
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_envelope_and_frequency_of_chirp() {
+        // A linear chirp from 200 Hz to 8 kHz whose amplitude rises with frequency,
+        // faded in and out over 50 ms as an abrupt start rings through the transform.
+        let sample_rate = 48000.0;
+        let (start, end) = (200.0, 8000.0);
+        let amplitude = |f: f32| 0.2 + 0.6 * (f - start) / (end - start);
+        let samples: Vec<f32> = (0..sample_rate as usize)
+            .map(|i| {
+                let t = i as f32 / sample_rate;
+                let f = start + (end - start) * t;
+                let fade = (t.min(1.0 - t) / 0.05).min(1.0);
+                let fade = (fade * PI / 2.0).sin().powi(2);
+                // The phase is computed in f64, f32 is too coarse for its derivative.
+                let t = i as f64 / sample_rate as f64;
+                let phase = 2.0 * std::f64::consts::PI * (200.0 * t + 7800.0 * t * t / 2.0);
+                fade * amplitude(f) * phase.sin() as f32
+            })
+            .collect();
+
+        let analytic = analytic_signal(&samples);
+        assert_eq!(analytic.len(), samples.len());
+        for (c, s) in analytic.iter().zip(&samples) {
+            assert!((c.re - s).abs() < 1e-4);
+        }
+
+        let result = instantaneous(&samples, sample_rate);
+        // Past the fades.
+        for i in (4800..43200).step_by(100) {
+            let expected = start + (end - start) * i as f32 / sample_rate;
+            assert!(
+                (result.frequency[i] - expected).abs() < 0.1,
+                "Expected frequency: {}, but got: {}",
+                expected,
+                result.frequency[i]
+            );
+            assert!((result.envelope[i] - amplitude(expected)).abs() < 0.01);
+        }
+
+        let band = Band {
+            min: 1000.0,
+            max: 7000.0,
+        };
+        let curve = result.response_curve(band, 60);
+        assert_eq!(curve.len(), 60);
+        for point in curve {
+            let expected = 20.0 * amplitude(point[0]).log10();
+            assert!(
+                (point[1] - expected).abs() < 0.1,
+                "Expected level: {}, but got: {}",
+                expected,
+                point[1]
+            );
+        }
+    }
+}
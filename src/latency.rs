@@ -0,0 +1,140 @@
+use rustfft::num_complex::Complex;
+
+use crate::fft::FftEngine;
+use crate::freq::{self, Interpolation};
+
+/// Latency is the delay of a capture behind the excitation that was played.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Latency {
+    /// Delay in samples, including a fractional part.
+    pub samples: f32,
+    pub seconds: f32,
+    /// Normalized cross-correlation at the nearest whole lag, 1 when the capture is a
+    /// delayed and scaled copy of the excitation.
+    pub correlation: f32,
+}
+
+/// Estimates the delay of `capture` behind `excitation` from the peak of their
+/// cross-correlation, refined between samples with a parabola. Returns None if
+/// either signal is silent.
+pub fn estimate(excitation: &[f32], capture: &[f32], sample_rate: f32) -> Option<Latency> {
+    let energy: f32 = excitation.iter().map(|x| x * x).sum();
+    if energy <= 0.0 || capture.is_empty() {
+        return None;
+    }
+    // Correlating is convolving with the time reversed excitation, lag k lands at
+    // index k + excitation.len() - 1.
+    let reversed: Vec<f32> = excitation.iter().rev().cloned().collect();
+    let correlation = freq::convolve(capture, &reversed);
+    let magnitudes: Vec<f32> = correlation[excitation.len() - 1..]
+        .iter()
+        .take(capture.len())
+        .map(|r| r.abs())
+        .collect();
+    let lag = (0..magnitudes.len()).max_by(|&a, &b| magnitudes[a].total_cmp(&magnitudes[b]))?;
+    let overlap: f32 = capture[lag..(lag + excitation.len()).min(capture.len())]
+        .iter()
+        .map(|x| x * x)
+        .sum();
+    if overlap <= 0.0 {
+        return None;
+    }
+
+    let samples =
+        lag as f32 + freq::interpolate_peak(&[], &magnitudes, lag, Interpolation::Parabolic);
+    Some(Latency {
+        samples,
+        seconds: samples / sample_rate,
+        correlation: magnitudes[lag] / (energy * overlap).sqrt(),
+    })
+}
+
+/// Returns `capture` advanced by `latency`, so that it lines up with the excitation.
+///
+/// The whole samples of the delay are dropped and the fraction left is removed with
+/// a linear phase shift in the frequency domain.
+pub fn align(capture: &[f32], latency: &Latency) -> Vec<f32> {
+    let whole = latency.samples.max(0.0).floor();
+    let samples = &capture[(whole as usize).min(capture.len())..];
+    let fraction = (latency.samples.max(0.0) - whole) as f64;
+    if fraction == 0.0 || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    // Zero-padded to twice the length so that the circular shift does not wrap.
+    let fft_len = (samples.len() * 2).next_power_of_two();
+    let mut buffer = samples.to_vec();
+    buffer.resize(fft_len, 0.0);
+    let engine = FftEngine::shared();
+    let mut bins = engine.rfft(&mut buffer);
+    // Bin k is k cycles per `fft_len` samples.
+    for (k, bin) in bins.iter_mut().enumerate() {
+        let phase = 2.0 * std::f64::consts::PI * k as f64 * fraction / fft_len as f64;
+        *bin *= Complex::new(phase.cos() as f32, phase.sin() as f32);
+    }
+    engine
+        .irfft(&mut bins, fft_len)
+        .iter()
+        .take(samples.len())
+        .map(|x| x / fft_len as f32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::noise;
+    use std::f32::consts::PI;
+
+    /// A faded linear chirp from 200 Hz to 8 kHz, evaluated `delay` samples late.
+    fn chirp(len: usize, delay: f32, sample_rate: f32) -> Vec<f32> {
+        let duration = 0.1;
+        let rate = (8000.0 - 200.0) / duration;
+        (0..len)
+            .map(|i| {
+                let t = (i as f32 - delay) / sample_rate;
+                if !(0.0..duration).contains(&t) {
+                    return 0.0;
+                }
+                let fade = (PI * t / duration).sin();
+                fade * (2.0 * PI * (200.0 * t + rate * t * t / 2.0)).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_latency_of_delayed_chirp() {
+        let sample_rate = 48000.0;
+        let delay = 1234.3;
+        let excitation = chirp(4800, 0.0, sample_rate);
+        let capture: Vec<f32> = chirp(12000, delay, sample_rate)
+            .into_iter()
+            .zip(noise(11, 12000))
+            .map(|(x, n)| -0.5 * x + 0.001 * n)
+            .collect();
+
+        let latency = estimate(&excitation, &capture, sample_rate).unwrap();
+        assert!(
+            (latency.samples - delay).abs() < 0.05,
+            "Expected delay: {}, but got: {}",
+            delay,
+            latency.samples
+        );
+        assert!((latency.seconds - delay / sample_rate).abs() < 1e-6);
+        assert!(latency.correlation > 0.95);
+
+        let aligned = align(&capture, &latency);
+        assert_eq!(aligned.len(), capture.len() - 1234);
+        let error = excitation
+            .iter()
+            .zip(&aligned)
+            .fold(0.0_f32, |m, (x, y)| m.max((y + 0.5 * x).abs()));
+        assert!(
+            error < 0.01,
+            "Expected aligned capture, but got error: {}",
+            error
+        );
+
+        assert!(estimate(&excitation, &vec![0.0; 1000], sample_rate).is_none());
+    }
+}
@@ -5,10 +5,30 @@ mod audio;
 mod calibrate;
 mod chirp;
 mod detect;
+mod distortion;
+mod fft;
+mod filter;
 mod freq;
+mod frf;
+mod goertzel;
+mod hilbert;
+mod latency;
+mod modal;
+mod octave;
+mod parametric;
+mod peaks;
+mod preprocess;
+mod resample;
+mod ringdown;
+mod spectrogram;
+mod sweep;
 mod task;
+#[cfg(test)]
+mod test_util;
 mod utils;
 mod wave;
+mod weighting;
+mod window;
 
 use utils::Result;
 
@@ -20,21 +40,21 @@ struct MainUI {
     status_timeout: std::time::Duration,
     status_updated_at: std::time::Instant,
     status_rx: tokio::sync::mpsc::Receiver<String>,
-    status_tx: tokio::sync::mpsc::Sender<String>,
+    _status_tx: tokio::sync::mpsc::Sender<String>,
 }
 
 impl MainUI {
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>) -> Self {
         let (status_tx, status_rx) = tokio::sync::mpsc::channel::<String>(1);
         Self {
             selected_tab: 0, // Default on the calibration page.
-            detect_tab: detect::DetectTab::new(status_tx.clone()),
-            calibrate_tab: calibrate::CalibrateTab::new(_cc, status_tx.clone()),
+            detect_tab: detect::DetectTab::new(cc, status_tx.clone()),
+            calibrate_tab: calibrate::CalibrateTab::new(cc, status_tx.clone()),
             status: "Running".to_string(),
             status_timeout: std::time::Duration::from_secs(3),
             status_updated_at: std::time::Instant::now(),
             status_rx,
-            status_tx,
+            _status_tx: status_tx,
         }
     }
 
@@ -68,11 +88,9 @@ impl MainUI {
                 .render(ui, ctx, _frame)
                 .unwrap_or_else(|e| {
                     self.status = e.to_string();
-                    ()
                 }),
             1 => self.detect_tab.render(ui, ctx, _frame).unwrap_or_else(|e| {
                 self.status = e.to_string();
-                ()
             }),
             _ => (),
         });
@@ -100,6 +118,11 @@ impl MainUI {
 }
 
 impl eframe::App for MainUI {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.calibrate_tab.save(storage);
+        self.detect_tab.save(storage);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         let visuals = egui::Visuals::light();
         ctx.set_visuals(visuals);
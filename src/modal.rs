@@ -0,0 +1,387 @@
+use rustfft::num_complex::Complex;
+
+use crate::freq::Band;
+use crate::frf::Frf;
+
+/// ModalSettings controls the curve fit of `fit`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModalSettings {
+    /// Band of the FRF that is fitted.
+    pub band: Band,
+    /// Number of modes in the band.
+    pub modes: usize,
+    /// Number of reweighting passes of the rational fraction fit.
+    pub iterations: usize,
+}
+
+impl Default for ModalSettings {
+    fn default() -> Self {
+        Self {
+            band: Band {
+                min: 20.0,
+                max: 20000.0,
+            },
+            modes: 2,
+            iterations: 10,
+        }
+    }
+}
+
+/// Mode is one fitted mode of vibration, the pole pair lambda, conj(lambda) with
+/// residues r, conj(r).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Mode {
+    /// Undamped natural frequency in Hz.
+    pub frequency: f32,
+    pub damping_ratio: f32,
+    /// Residue of the pole in the upper half plane, in FRF units times rad/s.
+    pub residue: Complex<f32>,
+}
+
+/// ModalFit is the outcome of a curve fit of an FRF.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModalFit {
+    pub modes: Vec<Mode>,
+    /// Constant term standing for the modes outside the band.
+    pub residual: Complex<f32>,
+    /// The fitted model evaluated over the band, without coherence.
+    pub synthesized: Frf,
+    /// RMS of the fit error relative to the RMS of the measured FRF in the band.
+    pub error: f32,
+}
+
+impl ModalFit {
+    /// Returns the modes as a header and one row per mode, for export.
+    pub fn table(&self) -> (Vec<String>, Vec<Vec<f32>>) {
+        let header = [
+            "Frequency (Hz)",
+            "Damping ratio",
+            "Q",
+            "Residue (real)",
+            "Residue (imaginary)",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let rows = self
+            .modes
+            .iter()
+            .map(|mode| {
+                vec![
+                    mode.frequency,
+                    mode.damping_ratio,
+                    1.0 / (2.0 * mode.damping_ratio),
+                    mode.residue.re,
+                    mode.residue.im,
+                ]
+            })
+            .collect();
+        (header, rows)
+    }
+}
+
+/// Fits `settings.modes` modes to the FRF in `settings.band` with the rational
+/// fraction polynomial method, and returns None if the band holds too few bins or
+/// the fit is singular.
+///
+/// H(s) = B(s) / A(s) is linearized as B(s) - H A(s) = 0 and solved by least squares,
+/// reweighted by 1 / |A(s)| of the previous pass so that it converges to the fit of
+/// H itself (Sanathanan-Koerner). The modes are the complex roots of A, and their
+/// residues are then fitted with the poles held fixed.
+pub fn fit(frf: &Frf, settings: &ModalSettings) -> Option<ModalFit> {
+    let order = 2 * settings.modes.max(1);
+    let (frequencies, response): (Vec<f32>, Vec<Complex<f64>>) = frf
+        .frequencies
+        .iter()
+        .zip(&frf.response)
+        .filter(|(f, _)| **f > 0.0 && **f >= settings.band.min && **f <= settings.band.max)
+        .map(|(f, h)| (*f, Complex::new(h.re as f64, h.im as f64)))
+        .unzip();
+    if frequencies.len() < 2 * order + 1 {
+        return None;
+    }
+    // Frequencies are normalized to the top of the band to keep the powers of s in
+    // range.
+    let scale = 2.0 * std::f64::consts::PI * *frequencies.last()? as f64;
+    let s: Vec<Complex<f64>> = frequencies
+        .iter()
+        .map(|f| Complex::new(0.0, 2.0 * std::f64::consts::PI * *f as f64 / scale))
+        .collect();
+
+    // A is monic, so the unknowns are b_0..b_order and a_0..a_(order-1).
+    let mut weights = vec![1.0; s.len()];
+    let mut denominator = Vec::new();
+    for _ in 0..settings.iterations.max(1) {
+        let mut columns = Vec::with_capacity(2 * order + 1);
+        for i in 0..=order {
+            columns.push(
+                s.iter()
+                    .zip(&weights)
+                    .map(|(s, w)| s.powu(i as u32) * w)
+                    .collect(),
+            );
+        }
+        for i in 0..order {
+            columns.push(
+                s.iter()
+                    .zip(&response)
+                    .zip(&weights)
+                    .map(|((s, h), w)| -h * s.powu(i as u32) * w)
+                    .collect(),
+            );
+        }
+        let target: Vec<Complex<f64>> = s
+            .iter()
+            .zip(&response)
+            .zip(&weights)
+            .map(|((s, h), w)| h * s.powu(order as u32) * w)
+            .collect();
+        let solution = least_squares(&columns, &target)?;
+        denominator = solution[order + 1..].to_vec();
+        denominator.push(1.0);
+        weights = s
+            .iter()
+            .map(|s| 1.0 / polynomial(&denominator, *s).norm().max(f64::MIN_POSITIVE))
+            .collect();
+    }
+
+    let mut poles: Vec<Complex<f64>> = roots(&denominator)
+        .into_iter()
+        .filter(|p| p.im > 0.0)
+        .map(|p| p * scale)
+        .collect();
+    poles.sort_by(|a, b| a.norm().total_cmp(&b.norm()));
+    if poles.is_empty() {
+        return None;
+    }
+
+    // Residues with the poles fixed: every mode adds r / (s - p) + conj(r) / (s -
+    // conj(p)), linear in the real and imaginary parts of r.
+    let s: Vec<Complex<f64>> = s.iter().map(|s| s * scale).collect();
+    let j = Complex::new(0.0, 1.0);
+    let mut columns: Vec<Vec<Complex<f64>>> = Vec::new();
+    for pole in &poles {
+        columns.push(
+            s.iter()
+                .map(|s| 1.0 / (s - pole) + 1.0 / (s - pole.conj()))
+                .collect(),
+        );
+        columns.push(
+            s.iter()
+                .map(|s| j / (s - pole) - j / (s - pole.conj()))
+                .collect(),
+        );
+    }
+    columns.push(vec![Complex::new(1.0, 0.0); s.len()]);
+    columns.push(vec![j; s.len()]);
+    let solution = least_squares(&columns, &response)?;
+    let residues: Vec<Complex<f64>> = solution
+        .chunks_exact(2)
+        .map(|c| Complex::new(c[0], c[1]))
+        .collect();
+    let residual = residues[poles.len()];
+
+    let synthesized: Vec<Complex<f64>> = s
+        .iter()
+        .map(|s| {
+            poles
+                .iter()
+                .zip(&residues)
+                .map(|(p, r)| r / (s - p) + r.conj() / (s - p.conj()))
+                .sum::<Complex<f64>>()
+                + residual
+        })
+        .collect();
+    let error: f64 = synthesized
+        .iter()
+        .zip(&response)
+        .map(|(a, b)| (a - b).norm_sqr())
+        .sum();
+    let power: f64 = response.iter().map(|h| h.norm_sqr()).sum();
+
+    let to_f32 = |c: Complex<f64>| Complex::new(c.re as f32, c.im as f32);
+    Some(ModalFit {
+        modes: poles
+            .iter()
+            .zip(&residues)
+            .map(|(p, r)| Mode {
+                frequency: (p.norm() / (2.0 * std::f64::consts::PI)) as f32,
+                damping_ratio: (-p.re / p.norm()) as f32,
+                residue: to_f32(*r),
+            })
+            .collect(),
+        residual: to_f32(residual),
+        synthesized: Frf {
+            frequencies,
+            response: synthesized.into_iter().map(to_f32).collect(),
+            coherence: Vec::new(),
+        },
+        error: (error / power.max(f64::MIN_POSITIVE)).sqrt() as f32,
+    })
+}
+
+/// Evaluates the polynomial with the given coefficients, constant term first.
+fn polynomial(coefficients: &[f64], x: Complex<f64>) -> Complex<f64> {
+    coefficients
+        .iter()
+        .rev()
+        .fold(Complex::new(0.0, 0.0), |acc, c| acc * x + c)
+}
+
+/// Returns the roots of the monic polynomial with the given coefficients, constant
+/// term first, with the Durand-Kerner iteration.
+fn roots(coefficients: &[f64]) -> Vec<Complex<f64>> {
+    let degree = coefficients.len().saturating_sub(1);
+    let seed = Complex::new(0.4, 0.9);
+    let mut roots: Vec<Complex<f64>> = (0..degree).map(|i| seed.powu(i as u32)).collect();
+    for _ in 0..1000 {
+        let mut largest_step: f64 = 0.0;
+        for i in 0..degree {
+            let mut divisor = Complex::new(1.0, 0.0);
+            for k in (0..degree).filter(|&k| k != i) {
+                divisor *= roots[i] - roots[k];
+            }
+            if divisor.norm() == 0.0 {
+                continue;
+            }
+            let step = polynomial(coefficients, roots[i]) / divisor;
+            roots[i] -= step;
+            largest_step = largest_step.max(step.norm());
+        }
+        if largest_step < 1e-14 {
+            break;
+        }
+    }
+    roots
+}
+
+/// Solves for the real `x` minimizing |sum_j x_j columns_j - target|, both sides
+/// complex, with a Householder QR factorization of the real and imaginary rows.
+fn least_squares(columns: &[Vec<Complex<f64>>], target: &[Complex<f64>]) -> Option<Vec<f64>> {
+    let (rows, n) = (2 * target.len(), columns.len());
+    if rows < n {
+        return None;
+    }
+    let mut a = vec![vec![0.0; n]; rows];
+    let mut b = vec![0.0; rows];
+    for (k, t) in target.iter().enumerate() {
+        (b[2 * k], b[2 * k + 1]) = (t.re, t.im);
+        for (j, column) in columns.iter().enumerate() {
+            (a[2 * k][j], a[2 * k + 1][j]) = (column[k].re, column[k].im);
+        }
+    }
+    // Columns are scaled to unit norm, as the powers of s span a wide range.
+    let scales: Vec<f64> = (0..n)
+        .map(|j| (0..rows).map(|i| a[i][j] * a[i][j]).sum::<f64>().sqrt())
+        .collect();
+    if scales.iter().any(|s| *s == 0.0) {
+        return None;
+    }
+    for row in a.iter_mut() {
+        for (x, scale) in row.iter_mut().zip(&scales) {
+            *x /= scale;
+        }
+    }
+
+    for j in 0..n {
+        let norm = (j..rows).map(|i| a[i][j] * a[i][j]).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            return None;
+        }
+        let alpha = if a[j][j] > 0.0 { -norm } else { norm };
+        let mut v: Vec<f64> = (j..rows).map(|i| a[i][j]).collect();
+        v[0] -= alpha;
+        let v_norm = v.iter().map(|x| x * x).sum::<f64>();
+        if v_norm == 0.0 {
+            continue;
+        }
+        for column in j..n {
+            let dot: f64 = (j..rows).map(|i| v[i - j] * a[i][column]).sum();
+            for i in j..rows {
+                a[i][column] -= 2.0 * dot / v_norm * v[i - j];
+            }
+        }
+        let dot: f64 = (j..rows).map(|i| v[i - j] * b[i]).sum();
+        for i in j..rows {
+            b[i] -= 2.0 * dot / v_norm * v[i - j];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for j in (0..n).rev() {
+        let sum: f64 = (j + 1..n).map(|k| a[j][k] * x[k]).sum();
+        x[j] = (b[j] - sum) / a[j][j];
+    }
+    Some(x.iter().zip(&scales).map(|(x, s)| x / s).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_fit_two_close_modes() {
+        // (frequency, damping ratio, residue)
+        let modes = [
+            (1000.0, 0.01, Complex::new(0.0, -500.0)),
+            (1030.0, 0.015, Complex::new(100.0, -300.0)),
+        ];
+        let pole = |f: f64, zeta: f64| {
+            let omega = 2.0 * PI * f;
+            Complex::new(-zeta * omega, omega * (1.0 - zeta * zeta).sqrt())
+        };
+        let frequencies: Vec<f32> = (0..4000).map(|f| f as f32).collect();
+        let response = frequencies
+            .iter()
+            .map(|f| {
+                let s = Complex::new(0.0, 2.0 * PI * *f as f64);
+                let h: Complex<f64> = modes
+                    .iter()
+                    .map(|(f, zeta, r)| {
+                        let p = pole(*f, *zeta);
+                        r / (s - p) + r.conj() / (s - p.conj())
+                    })
+                    .sum();
+                Complex::new(h.re as f32, h.im as f32)
+            })
+            .collect();
+        let frf = Frf {
+            frequencies,
+            response,
+            coherence: Vec::new(),
+        };
+        let settings = ModalSettings {
+            band: Band {
+                min: 900.0,
+                max: 1150.0,
+            },
+            ..Default::default()
+        };
+
+        let fit = fit(&frf, &settings).unwrap();
+        assert_eq!(fit.modes.len(), 2);
+        for (mode, (frequency, zeta, residue)) in fit.modes.iter().zip(modes) {
+            assert!(
+                (mode.frequency - frequency as f32).abs() < 0.05,
+                "Expected frequency: {}, but got: {}",
+                frequency,
+                mode.frequency
+            );
+            assert!((mode.damping_ratio - zeta as f32).abs() < 1e-4);
+            let residue = Complex::new(residue.re as f32, residue.im as f32);
+            assert!(
+                (mode.residue - residue).norm() < 0.01 * residue.norm(),
+                "Expected residue: {}, but got: {}",
+                residue,
+                mode.residue
+            );
+        }
+        assert!(fit.error < 0.01);
+        assert_eq!(fit.synthesized.frequencies.len(), 251);
+
+        let (header, rows) = fit.table();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(header.len(), rows[0].len());
+    }
+}
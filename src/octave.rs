@@ -0,0 +1,224 @@
+use std::fmt;
+
+use crate::freq::{Band, Psd, WelchSettings};
+use crate::weighting::Weighting;
+use crate::window::Window;
+
+/// Fraction is the width of the bands, as a fraction of an octave.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Fraction {
+    Octave,
+    #[default]
+    Third,
+    Sixth,
+    Twelfth,
+}
+
+impl fmt::Display for Fraction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "1/{} octave", self.bands_per_octave())
+    }
+}
+
+impl Fraction {
+    pub fn bands_per_octave(&self) -> usize {
+        match self {
+            Fraction::Octave => 1,
+            Fraction::Third => 3,
+            Fraction::Sixth => 6,
+            Fraction::Twelfth => 12,
+        }
+    }
+}
+
+/// OctaveBand is the level of one fractional-octave band.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OctaveBand {
+    /// Exact mid-band frequency in Hz.
+    pub center: f32,
+    /// Band edges in Hz.
+    pub lower: f32,
+    pub upper: f32,
+    /// Mean square in the band in dBFS, 0 dBFS being a full scale sine, or None when
+    /// the band is narrower than the resolution of the spectrum.
+    pub level_db: Option<f32>,
+}
+
+/// Returns the settings of a Welch PSD fine enough to resolve the narrowest bands, a
+/// twelfth of an octave wide, from `band.min` but not below 20 Hz.
+pub fn welch_settings(band: Band, sample_rate: f32, window: Window) -> WelchSettings {
+    let half_width = 10.0_f32.powf(0.3 / 24.0);
+    let width = band.min.max(20.0) * (half_width - 1.0 / half_width);
+    WelchSettings {
+        segment_len: ((sample_rate / width).ceil() as usize).next_power_of_two(),
+        window,
+        ..Default::default()
+    }
+}
+
+/// Returns the levels of the fractional-octave bands that fit in `band`, integrated
+/// from a power spectral density and corrected by `weighting` at the mid-band
+/// frequency.
+///
+/// Mid-band frequencies and edges follow the base ten system of IEC 61260-1, with
+/// 1 kHz as the reference. Bands narrower than the resolution of the PSD are kept
+/// without a level, and bins straddling an edge are split between the two bands.
+pub fn octave_bands(
+    psd: &Psd,
+    fraction: Fraction,
+    band: Band,
+    weighting: Weighting,
+) -> Vec<OctaveBand> {
+    let resolution = psd.resolution() as f64;
+    let Some(&last) = psd.frequencies.last() else {
+        return Vec::new();
+    };
+    if resolution <= 0.0 {
+        return Vec::new();
+    }
+    let top = (band.max as f64).min(last as f64 + resolution / 2.0);
+    let bottom = (band.min as f64).max(0.0);
+
+    let b = fraction.bands_per_octave() as f64;
+    let ratio = 10.0_f64.powf(0.3);
+    // With an even number of bands per octave the reference falls on a band edge.
+    let center = |x: i32| {
+        if fraction.bands_per_octave() % 2 == 1 {
+            1000.0 * ratio.powf(x as f64 / b)
+        } else {
+            1000.0 * ratio.powf((2 * x + 1) as f64 / (2.0 * b))
+        }
+    };
+    let half_width = ratio.powf(1.0 / (2.0 * b));
+
+    let mut bands = Vec::new();
+    let first = (b * (bottom.max(resolution) / 1000.0).ln() / ratio.ln()).floor() as i32 - 1;
+    for x in first.. {
+        let center = center(x);
+        let (lower, upper) = (center / half_width, center * half_width);
+        if upper > top {
+            break;
+        }
+        if lower < bottom {
+            continue;
+        }
+        if lower < resolution || upper - lower < resolution {
+            bands.push(OctaveBand {
+                center: center as f32,
+                lower: lower as f32,
+                upper: upper as f32,
+                level_db: None,
+            });
+            continue;
+        }
+        let start = ((lower / resolution).floor() as usize).min(psd.power.len());
+        let end = ((upper / resolution).ceil() as usize + 1).min(psd.power.len());
+        let mean_square: f64 = (start..end)
+            .map(|k| {
+                let f = psd.frequencies[k] as f64;
+                let overlap =
+                    (upper.min(f + resolution / 2.0) - lower.max(f - resolution / 2.0)).max(0.0);
+                psd.power[k] as f64 * overlap
+            })
+            .sum();
+        bands.push(OctaveBand {
+            center: center as f32,
+            lower: lower as f32,
+            upper: upper as f32,
+            level_db: Some(
+                10.0 * ((2.0 * mean_square) as f32).max(f32::MIN_POSITIVE).log10()
+                    + weighting.gain_db(center as f32),
+            ),
+        });
+    }
+    bands
+}
+
+/// Returns the bands as a header and one row per band, for export. Bands without a
+/// level are left empty.
+pub fn table(bands: &[OctaveBand], weighting: Weighting) -> (Vec<String>, Vec<Vec<f32>>) {
+    let header = vec![
+        "Center (Hz)".to_string(),
+        "Lower edge (Hz)".to_string(),
+        "Upper edge (Hz)".to_string(),
+        format!("Level (dBFS, {}-weighted)", weighting),
+    ];
+    let rows = bands
+        .iter()
+        .map(|band| {
+            vec![
+                band.center,
+                band.lower,
+                band.upper,
+                band.level_db.unwrap_or(f32::NAN),
+            ]
+        })
+        .collect();
+    (header, rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::freq::{self, WelchSettings};
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_third_octave_bands() {
+        let sample_rate = 48000.0;
+        let samples: Vec<f32> = (0..sample_rate as usize * 2)
+            .map(|i| 0.5 * (2.0 * PI * 1000.0 * i as f32 / sample_rate).sin())
+            .collect();
+        let psd = freq::welch(&samples, sample_rate, &WelchSettings::default());
+        let band = Band {
+            min: 20.0,
+            max: 20000.0,
+        };
+        let bands = octave_bands(&psd, Fraction::Third, band, Weighting::Z);
+
+        let tone = bands
+            .iter()
+            .find(|b| (b.center - 1000.0).abs() < 0.01)
+            .unwrap();
+        assert!((tone.lower - 891.25).abs() < 0.01);
+        assert!((tone.upper - 1122.02).abs() < 0.01);
+        let level = tone.level_db.unwrap();
+        assert!(
+            (level + 6.02).abs() < 0.1,
+            "Expected level: -6.02, but got: {}",
+            level
+        );
+        // The 25 Hz band is narrower than the 5.9 Hz bins, and kept without a level.
+        assert!((bands[0].center - 25.1).abs() < 0.1);
+        assert!(bands[0].level_db.is_none());
+        assert!(bands[1].level_db.is_some());
+        // The last band ends below 20 kHz, the one centred on 20 kHz does not fit.
+        let last = bands.last().unwrap();
+        assert!((last.center - 15848.9).abs() < 0.1);
+        for pair in bands.windows(2) {
+            assert!((pair[1].lower - pair[0].upper).abs() < 0.01);
+        }
+        for other in bands.iter().filter(|b| *b != tone) {
+            assert!(other.level_db.map_or(true, |level| level < -40.0));
+        }
+
+        let octaves = octave_bands(&psd, Fraction::Octave, band, Weighting::Z);
+        assert_eq!(octaves.len(), 9);
+        // Weighting is applied at the mid-band frequency.
+        let weighted = octave_bands(&psd, Fraction::Octave, band, Weighting::A);
+        for (a, z) in weighted.iter().zip(&octaves) {
+            let gain = Weighting::A.gain_db(z.center);
+            assert!((a.level_db.unwrap() - z.level_db.unwrap() - gain).abs() < 1e-3);
+        }
+        let sixths = octave_bands(&psd, Fraction::Sixth, band, Weighting::Z);
+        // 1 kHz is a band edge with an even number of bands per octave.
+        assert!(sixths.iter().any(|b| (b.upper - 1000.0).abs() < 0.01));
+
+        // A PSD fine enough for twelfth-octave bands gives every band a level.
+        let settings = welch_settings(band, sample_rate, Window::Hann);
+        let fine = freq::welch(&samples, sample_rate, &settings);
+        let twelfths = octave_bands(&fine, Fraction::Twelfth, band, Weighting::Z);
+        assert!(twelfths[0].lower >= 20.0);
+        assert!(twelfths.iter().all(|b| b.level_db.is_some()));
+    }
+}
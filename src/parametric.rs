@@ -0,0 +1,383 @@
+use std::f64::consts::PI;
+use std::fmt;
+
+use nalgebra::{DMatrix, DVector};
+use rustfft::num_complex::Complex;
+
+use crate::filter::Biquad;
+use crate::freq::{Band, Detection, NoResonance, Resonance};
+
+/// Rows of the covariance matrix of root-MUSIC, whose polynomial has twice as many
+/// roots.
+const MUSIC_ROWS: usize = 64;
+
+/// Samples of the decimated signal run through the low-pass filters before the
+/// segment, for their transient to die out.
+const SETTLE: usize = 64;
+
+/// Method is a high-resolution estimator of the damped sinusoids of a free response,
+/// which separates modes closer than the resolution of the FFT of the segment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Method {
+    /// Rotational invariance of the signal subspace.
+    #[default]
+    Esprit,
+    /// Roots of the polynomial of the noise subspace. Its damping is only indicative,
+    /// the roots move off the unit circle with the noise as well.
+    RootMusic,
+    /// Prony's linear prediction model, solved as a matrix pencil.
+    MatrixPencil,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Method::Esprit => write!(f, "ESPRIT"),
+            Method::RootMusic => write!(f, "root-MUSIC"),
+            Method::MatrixPencil => write!(f, "Prony (matrix pencil)"),
+        }
+    }
+}
+
+/// ParametricSettings controls the parametric estimation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParametricSettings {
+    pub method: Method,
+    /// Model order, as the number of real damped sinusoids.
+    pub modes: usize,
+    /// Number of samples analyzed, after decimation.
+    pub segment_len: usize,
+    /// Only one sample in `decimation` is kept, after a low-pass filter. The modes
+    /// then sit further from DC, where the estimators are better conditioned, and a
+    /// segment of the same length spans a longer time.
+    pub decimation: usize,
+}
+
+impl Default for ParametricSettings {
+    fn default() -> Self {
+        Self {
+            method: Method::default(),
+            modes: 2,
+            segment_len: 1024,
+            decimation: 4,
+        }
+    }
+}
+
+/// Component is one damped sinusoid of the model.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Component {
+    pub frequency: f32,
+    pub damping_ratio: f32,
+    /// Amplitude at the start of the segment, 1 for a full scale sine.
+    pub amplitude: f32,
+    /// Standard deviation of the frequency in Hz, from the Cramér-Rao bound of an
+    /// undamped sine in the residual of the model.
+    pub uncertainty: f32,
+    /// Height of the component above the residual in dB, as it would show in the
+    /// spectrum of the segment.
+    pub snr_db: f32,
+}
+
+/// Fits `settings.modes` damped sinusoids to the segment of `samples` starting at
+/// `start`, and returns them from the strongest to the weakest.
+///
+/// The segment should hold a free response, such as the ring-down after the
+/// excitation stopped, or the estimators lock onto the excitation.
+pub fn estimate(
+    samples: &[f32],
+    start: usize,
+    sample_rate: f32,
+    settings: &ParametricSettings,
+) -> Result<Vec<Component>, NoResonance> {
+    let step = settings.decimation.max(1);
+    let order = 2 * settings.modes.max(1);
+    let segment = decimate(samples, start, sample_rate, step, settings.segment_len);
+    if segment.len() < 4 * order {
+        return Err(NoResonance::TooFewSamples);
+    }
+    let poles = match settings.method {
+        Method::Esprit => esprit(&segment, order),
+        Method::RootMusic => root_music(&segment, order),
+        Method::MatrixPencil => matrix_pencil(&segment, order),
+    };
+    let Some(amplitudes) = amplitudes(&segment, &poles) else {
+        return Err(NoResonance::TooFewSamples);
+    };
+
+    // Mean square of what the model leaves out.
+    let len = segment.len();
+    let noise = (0..len)
+        .map(|t| {
+            let model: f64 = poles
+                .iter()
+                .zip(&amplitudes)
+                .map(|(z, a)| (a * z.powi(t as i32)).re)
+                .sum();
+            (segment[t] - model).powi(2)
+        })
+        .sum::<f64>()
+        / len as f64;
+
+    let rate = sample_rate as f64 / step as f64;
+    let mut components: Vec<Component> = poles
+        .iter()
+        .zip(&amplitudes)
+        // A real signal has a pole and its conjugate per sinusoid, the upper half
+        // plane keeps one of each. Real poles are offsets and drifts.
+        .filter(|(z, _)| z.im > 0.0 && z.norm() > 0.0)
+        .map(|(z, a)| {
+            let s = z.ln() * rate;
+            let amplitude = 2.0 * a.norm();
+            let snr = amplitude * amplitude / (2.0 * noise.max(f64::MIN_POSITIVE));
+            let n = len as f64;
+            Component {
+                frequency: (s.im / (2.0 * PI)) as f32,
+                damping_ratio: (-s.re / s.norm()) as f32,
+                amplitude: amplitude as f32,
+                uncertainty: (rate / (2.0 * PI) * (12.0 / (snr * n * (n * n - 1.0))).sqrt()) as f32,
+                snr_db: (10.0 * (snr * n / 2.0).log10()) as f32,
+            }
+        })
+        .collect();
+    components.sort_by(|a, b| b.amplitude.total_cmp(&a.amplitude));
+    Ok(components)
+}
+
+/// Returns the strongest component in `band` as the resonance, or why there is none
+/// that passes `detection`.
+pub fn resonance(
+    components: &[Component],
+    band: Band,
+    detection: &Detection,
+) -> Result<Resonance, NoResonance> {
+    let strongest = components
+        .iter()
+        .filter(|c| c.frequency >= band.min && c.frequency <= band.max)
+        .max_by(|a, b| a.amplitude.total_cmp(&b.amplitude))
+        .ok_or(NoResonance::EmptyBand)?;
+    let level_db = 20.0 * strongest.amplitude.max(f32::MIN_POSITIVE).log10();
+    if level_db < detection.min_level_db {
+        return Err(NoResonance::Silent { level_db });
+    }
+    if strongest.snr_db < detection.min_snr_db {
+        return Err(NoResonance::BelowNoise {
+            snr_db: strongest.snr_db,
+        });
+    }
+    Ok(Resonance {
+        frequency: strongest.frequency,
+        uncertainty: strongest.uncertainty,
+        snr_db: strongest.snr_db,
+    })
+}
+
+/// Returns up to `len` samples of `samples` from `start`, decimated by `step`.
+///
+/// The anti-aliasing filter is four second order low-pass sections at 80% of the
+/// new Nyquist frequency, started a little before the segment.
+fn decimate(samples: &[f32], start: usize, sample_rate: f32, step: usize, len: usize) -> Vec<f64> {
+    if step == 1 {
+        let segment = &samples[start.min(samples.len())..];
+        return segment.iter().take(len).map(|&s| s as f64).collect();
+    }
+    let lead = (start / step).min(SETTLE) * step;
+    let end = start
+        .saturating_add(len.saturating_mul(step))
+        .min(samples.len());
+    let mut filtered = samples[(start - lead).min(end)..end].to_vec();
+    let low_pass = Biquad::low_pass(
+        0.4 * sample_rate / step as f32,
+        std::f32::consts::FRAC_1_SQRT_2,
+        sample_rate,
+    );
+    for _ in 0..4 {
+        filtered = low_pass.process(&filtered);
+    }
+    filtered
+        .iter()
+        .skip(lead)
+        .step_by(step)
+        .map(|&s| s as f64)
+        .collect()
+}
+
+/// Returns the Hankel matrix of `x` with `rows` rows, whose columns are the windows
+/// of `rows` successive samples.
+fn hankel(x: &[f64], rows: usize) -> DMatrix<f64> {
+    DMatrix::from_fn(rows, x.len() - rows + 1, |i, j| x[i + j])
+}
+
+/// Returns the eigenvectors of the symmetric matrix `m`, sorted by decreasing
+/// eigenvalue.
+fn eigenvectors(m: DMatrix<f64>) -> DMatrix<f64> {
+    let eigen = m.symmetric_eigen();
+    let mut order: Vec<usize> = (0..eigen.eigenvalues.len()).collect();
+    order.sort_by(|&a, &b| eigen.eigenvalues[b].total_cmp(&eigen.eigenvalues[a]));
+    DMatrix::from_columns(
+        &order
+            .iter()
+            .map(|&i| eigen.eigenvectors.column(i))
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Returns the eigenvalues of the matrix that shifts the rows of `basis` up by one,
+/// in the least squares sense. They are the poles of the exponentials spanning it.
+fn shift_invariance(basis: &DMatrix<f64>) -> Vec<Complex<f64>> {
+    let rows = basis.nrows() - 1;
+    let (upper, lower) = (basis.rows(0, rows), basis.rows(1, rows));
+    match upper.svd(true, true).solve(&lower, 1e-12) {
+        Ok(shift) => shift.complex_eigenvalues().iter().copied().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn esprit(x: &[f64], order: usize) -> Vec<Complex<f64>> {
+    let data = hankel(x, x.len() / 3);
+    let covariance = &data * data.transpose();
+    let signal = eigenvectors(covariance).columns(0, order).into_owned();
+    shift_invariance(&signal)
+}
+
+fn matrix_pencil(x: &[f64], order: usize) -> Vec<Complex<f64>> {
+    // The pencil parameter is a third of the length, the rows of the data matrix
+    // are the windows of one more sample.
+    let data = hankel(x, x.len() / 3 + 1).transpose();
+    let svd = data.svd(false, true);
+    let Some(v_t) = svd.v_t else {
+        return Vec::new();
+    };
+    // Right singular vectors of the largest singular values, sorted by `svd`.
+    let signal = v_t.rows(0, order).transpose();
+    shift_invariance(&signal)
+}
+
+fn root_music(x: &[f64], order: usize) -> Vec<Complex<f64>> {
+    let rows = (x.len() / 3).min(MUSIC_ROWS);
+    let data = hankel(x, rows);
+    let vectors = eigenvectors(&data * data.transpose());
+    let noise = vectors.columns(order, rows - order);
+    let projector = noise * noise.transpose();
+
+    // a(1/z)^T P a(z) with a(z) = [1, z, ..., z^(rows - 1)], times z^(rows - 1),
+    // the coefficient of z^(k + rows - 1) being the sum of the k-th diagonal of P.
+    // P is symmetric, so are the coefficients.
+    let degree = 2 * (rows - 1);
+    let coefficients: Vec<f64> = (0..=degree)
+        .map(|m| {
+            let k = m.abs_diff(rows - 1);
+            (0..rows - k).map(|i| projector[(i, i + k)]).sum()
+        })
+        .collect();
+    let companion = DMatrix::from_fn(degree, degree, |i, j| {
+        if i == 0 {
+            -coefficients[degree - 1 - j] / coefficients[degree]
+        } else if i == j + 1 {
+            1.0
+        } else {
+            0.0
+        }
+    });
+
+    // The roots come in pairs z and 1 / z*; the signal ones are the pairs closest
+    // to the unit circle.
+    let mut roots: Vec<Complex<f64>> = companion
+        .complex_eigenvalues()
+        .iter()
+        .copied()
+        .filter(|z| z.norm() < 1.0)
+        .collect();
+    roots.sort_by(|a, b| b.norm().total_cmp(&a.norm()));
+    roots.truncate(order);
+    roots
+}
+
+/// Returns the complex amplitudes of the exponentials `poles` that fit `x` best,
+/// in the least squares sense.
+fn amplitudes(x: &[f64], poles: &[Complex<f64>]) -> Option<Vec<Complex<f64>>> {
+    if poles.is_empty() {
+        return None;
+    }
+    let vandermonde = DMatrix::from_fn(x.len(), poles.len(), |t, k| poles[k].powi(t as i32));
+    let target = DVector::from_fn(x.len(), |t, _| Complex::new(x[t], 0.0));
+    let solution = vandermonde.svd(true, true).solve(&target, 1e-12).ok()?;
+    Some(solution.iter().copied().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::noise;
+
+    #[test]
+    fn test_separate_two_close_modes() {
+        // Two decaying modes 30 Hz apart in light noise, analyzed over 10 ms, three
+        // times less than the resolution of their FFT. The segment starts once the
+        // decimation filters have settled.
+        let sample_rate = 192000.0;
+        let modes: [(f64, f64, f64); 2] = [(1000.0, 0.004, 0.5), (1030.0, 0.008, 0.3)];
+        let samples: Vec<f32> = noise(1, 4096)
+            .into_iter()
+            .enumerate()
+            .map(|(i, noise)| {
+                let t = i as f64 / sample_rate as f64;
+                let signal: f64 = modes
+                    .iter()
+                    .map(|(f, zeta, a)| {
+                        let omega = 2.0 * PI * f;
+                        a * (-zeta * omega * t).exp() * (omega * t).sin()
+                    })
+                    .sum();
+                signal as f32 + 1e-4 * noise
+            })
+            .collect();
+
+        for method in [Method::Esprit, Method::RootMusic, Method::MatrixPencil] {
+            let settings = ParametricSettings {
+                method,
+                modes: 2,
+                segment_len: 480,
+                decimation: 4,
+            };
+            let start = 1024;
+            let components = estimate(&samples, start, sample_rate, &settings).unwrap();
+            assert_eq!(components.len(), 2, "{}", method);
+            for (component, (f, zeta, a)) in components.iter().zip(modes) {
+                assert!(
+                    (component.frequency - f as f32).abs() < 0.5,
+                    "Expected {} frequency: {}, but got: {}",
+                    method,
+                    f,
+                    component.frequency
+                );
+                if method != Method::RootMusic {
+                    assert!(
+                        (component.damping_ratio - zeta as f32).abs() < 0.0005,
+                        "Expected {} damping ratio: {}, but got: {}",
+                        method,
+                        zeta,
+                        component.damping_ratio
+                    );
+                    let decay = (-zeta * 2.0 * PI * f * start as f64 / sample_rate as f64).exp();
+                    assert!((component.amplitude - (a * decay) as f32).abs() < 0.01);
+                }
+            }
+
+            let band = Band {
+                min: 20.0,
+                max: 20000.0,
+            };
+            let resonance = resonance(&components, band, &Detection::default()).unwrap();
+            assert_eq!(resonance.frequency, components[0].frequency);
+            assert!(resonance.uncertainty < 0.1);
+        }
+
+        // A segment past the end of the capture has nothing to fit.
+        let settings = ParametricSettings::default();
+        assert_eq!(
+            estimate(&samples, 4090, sample_rate, &settings),
+            Err(NoResonance::TooFewSamples)
+        );
+    }
+}
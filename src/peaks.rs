@@ -0,0 +1,535 @@
+use std::ops::Range;
+
+use rustfft::num_complex::Complex;
+
+use crate::freq::{self, Band, Detection, Estimator, Interpolation, Psd};
+use crate::frf::Frf;
+use crate::window::Window;
+
+/// Peak is a resonance found in the magnitude spectrum.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Peak {
+    /// Interpolated frequency of the peak in Hz.
+    pub frequency: f32,
+    /// Amplitude of the peak in dBFS.
+    pub magnitude_db: f32,
+    /// Height of the peak above the higher of its two surrounding minima, in dB.
+    pub prominence: f32,
+    /// Height of the peak above the noise floor of the spectrum, in dB.
+    pub snr_db: f32,
+    /// Half-power bandwidth, Q factor and damping ratio of the peak.
+    pub damping: Damping,
+    /// Coherence between excitation and response at the peak, when known.
+    pub coherence: Option<f32>,
+}
+
+impl Peak {
+    /// Returns true if the peak coherence is known and below `settings.min_coherence`.
+    pub fn is_incoherent(&self, settings: &PeakSettings) -> bool {
+        self.coherence
+            .is_some_and(|coherence| coherence < settings.min_coherence)
+    }
+
+    /// Returns true if the peak is less than `detection.min_snr_db` above the noise
+    /// floor.
+    pub fn is_below_noise(&self, detection: &Detection) -> bool {
+        self.snr_db < detection.min_snr_db
+    }
+}
+
+/// Damping describes the sharpness of a peak from its half-power (-3 dB) points.
+///
+/// The points are measured on the unwindowed spectrum of the whole capture, so the
+/// bandwidth is that of the resonance and not of the analysis window mainlobe or of
+/// the Welch segment bins. It is resolved down to about one bin of the capture length,
+/// which is also what a steady tone reads.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Damping {
+    /// Lower -3 dB frequency in Hz.
+    pub lower: f32,
+    /// Upper -3 dB frequency in Hz.
+    pub upper: f32,
+    /// Half-power bandwidth in Hz.
+    pub bandwidth: f32,
+    /// Quality factor, the peak frequency over the bandwidth.
+    pub q: f32,
+    /// Damping ratio, 1 / (2 Q).
+    pub ratio: f32,
+    /// False if a -3 dB point was not reached inside the analyzed band, in which case
+    /// the band edge is used and the bandwidth is underestimated.
+    pub in_band: bool,
+}
+
+/// PeakSettings controls which peaks `find_peaks` reports.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeakSettings {
+    /// Maximum number of peaks to return.
+    pub max_peaks: usize,
+    /// Minimum distance in Hz between two reported peaks.
+    pub min_spacing: f32,
+    /// Peaks below this level in dBFS are ignored.
+    pub threshold_db: f32,
+    /// Peaks whose coherence is below this value are flagged as unreliable.
+    pub min_coherence: f32,
+    /// Drop the flagged peaks instead of only flagging them.
+    pub reject_incoherent: bool,
+}
+
+impl Default for PeakSettings {
+    fn default() -> Self {
+        Self {
+            max_peaks: 5,
+            min_spacing: 20.0,
+            threshold_db: -90.0,
+            min_coherence: 0.8,
+            reject_incoherent: false,
+        }
+    }
+}
+
+/// Zero-padding of the unwindowed spectrum the half-power points are measured on, for
+/// the linear interpolation between bins to follow the peak shape.
+const DAMPING_PADDING: usize = 4;
+
+/// Returns the strongest resonances of `samples`, sorted by decreasing magnitude.
+pub fn find_peaks(
+    samples: &[f32],
+    sample_rate: f32,
+    estimator: &Estimator,
+    settings: &PeakSettings,
+) -> Vec<Peak> {
+    let num_samples = samples.len();
+    if num_samples < 3 {
+        return Vec::new();
+    }
+    let fft_len = num_samples * estimator.zero_padding.max(1);
+    let freq_of_resolution = sample_rate / fft_len as f32;
+
+    let bins = freq::spectrum(samples, estimator.window, estimator.zero_padding);
+    let magnitudes: Vec<f32> = bins.iter().map(|c| c.norm()).collect();
+    let scale = 2.0 * estimator.window.amplitude_correction(num_samples) / num_samples as f32;
+    let db: Vec<f32> = magnitudes
+        .iter()
+        .map(|m| 20.0 * (m * scale).max(f32::MIN_POSITIVE).log10())
+        .collect();
+
+    let peaks = pick_peaks(
+        &bins,
+        &magnitudes,
+        &db,
+        freq_of_resolution,
+        estimator.effective_interpolation(),
+        estimator.band.bins(freq_of_resolution, db.len()),
+        settings,
+    );
+    let peaks = with_damping(peaks, samples, sample_rate, estimator.band);
+    with_snr(peaks, estimator.detection.noise_floor_db(&db))
+}
+
+/// Returns the strongest resonances of a Welch power spectral density of `samples`,
+/// sorted by decreasing magnitude. Magnitudes are reported as the level of an
+/// equivalent tone, and the damping is measured on the full-resolution spectrum.
+pub fn find_peaks_in_psd(
+    samples: &[f32],
+    sample_rate: f32,
+    psd: &Psd,
+    interpolation: Interpolation,
+    band: Band,
+    detection: &Detection,
+    settings: &PeakSettings,
+) -> Vec<Peak> {
+    if psd.power.len() < 3 {
+        return Vec::new();
+    }
+    // Quinn's estimator needs the complex bins, which the PSD no longer has.
+    let interpolation = match interpolation {
+        Interpolation::Quinn => Interpolation::Gaussian,
+        other => other,
+    };
+    let magnitudes: Vec<f32> = psd.power.iter().map(|p| p.sqrt()).collect();
+    let db = psd.tone_levels_db();
+    let peaks = pick_peaks(
+        &[],
+        &magnitudes,
+        &db,
+        psd.resolution(),
+        interpolation,
+        band.bins(psd.resolution(), db.len()),
+        settings,
+    );
+    let peaks = with_damping(peaks, samples, sample_rate, band);
+    with_snr(peaks, detection.noise_floor_db(&db))
+}
+
+/// Sets the damping of every peak from the half-power points of the unwindowed,
+/// zero-padded spectrum of `samples`, searched within `band`.
+fn with_damping(peaks: Vec<Peak>, samples: &[f32], sample_rate: f32, band: Band) -> Vec<Peak> {
+    let bins = freq::spectrum(samples, Window::Rectangular, DAMPING_PADDING);
+    let db: Vec<f32> = bins
+        .iter()
+        .map(|c| 20.0 * c.norm().max(f32::MIN_POSITIVE).log10())
+        .collect();
+    let freq_of_resolution = sample_rate / (samples.len() * DAMPING_PADDING) as f32;
+    let range = band.bins(freq_of_resolution, db.len());
+    peaks
+        .into_iter()
+        .map(|peak| {
+            // The peak bin of the finer spectrum, within a bin of the coarser ones.
+            let center = (peak.frequency / freq_of_resolution).round() as usize;
+            let nearby = center.saturating_sub(DAMPING_PADDING)
+                ..(center + DAMPING_PADDING + 1).min(db.len());
+            let Some(k) = nearby.max_by(|&a, &b| db[a].total_cmp(&db[b])) else {
+                return peak;
+            };
+            Peak {
+                damping: half_power_damping(
+                    &db,
+                    k,
+                    range.clone(),
+                    peak.frequency,
+                    freq_of_resolution,
+                ),
+                ..peak
+            }
+        })
+        .collect()
+}
+
+/// Sets the SNR of every peak from the noise floor of the spectrum in dBFS.
+fn with_snr(peaks: Vec<Peak>, noise_db: f32) -> Vec<Peak> {
+    peaks
+        .into_iter()
+        .map(|peak| Peak {
+            snr_db: peak.magnitude_db - noise_db,
+            ..peak
+        })
+        .collect()
+}
+
+fn pick_peaks(
+    bins: &[Complex<f32>],
+    magnitudes: &[f32],
+    db: &[f32],
+    freq_of_resolution: f32,
+    interpolation: Interpolation,
+    band: Range<usize>,
+    settings: &PeakSettings,
+) -> Vec<Peak> {
+    let mut candidates: Vec<usize> = (band.start.max(1)..band.end.min(db.len().saturating_sub(1)))
+        .filter(|&i| db[i] > db[i - 1] && db[i] >= db[i + 1] && db[i] >= settings.threshold_db)
+        .collect();
+    candidates.sort_by(|&a, &b| db[b].total_cmp(&db[a]));
+
+    let mut peaks: Vec<Peak> = Vec::new();
+    for k in candidates {
+        if peaks.len() >= settings.max_peaks {
+            break;
+        }
+        let offset = freq::interpolate_peak(bins, magnitudes, k, interpolation);
+        let frequency = (k as f32 + offset) * freq_of_resolution;
+        if peaks
+            .iter()
+            .any(|p| (p.frequency - frequency).abs() < settings.min_spacing)
+        {
+            continue;
+        }
+        peaks.push(Peak {
+            frequency,
+            magnitude_db: db[k],
+            prominence: prominence(db, k),
+            // Set by `with_snr`, once the noise floor is known.
+            snr_db: 0.0,
+            // Set by `with_damping`, from the unwindowed spectrum.
+            damping: Damping::default(),
+            coherence: None,
+        });
+    }
+    peaks
+}
+
+/// Looks up the coherence of every peak in `frf`, dropping the ones below
+/// `settings.min_coherence` if `settings.reject_incoherent` is set.
+pub fn check_coherence(peaks: Vec<Peak>, frf: &Frf, settings: &PeakSettings) -> Vec<Peak> {
+    peaks
+        .into_iter()
+        .map(|peak| Peak {
+            coherence: Some(frf.coherence_at(peak.frequency)),
+            ..peak
+        })
+        .filter(|peak| !settings.reject_incoherent || !peak.is_incoherent(settings))
+        .collect()
+}
+
+/// Returns the peak nearest to `frequency` among those whose half-power band
+/// contains it, or None if no peak spans it.
+pub fn at_frequency(peaks: &[Peak], frequency: f32) -> Option<&Peak> {
+    peaks
+        .iter()
+        .filter(|peak| (peak.damping.lower..=peak.damping.upper).contains(&frequency))
+        .min_by(|a, b| {
+            (a.frequency - frequency)
+                .abs()
+                .total_cmp(&(b.frequency - frequency).abs())
+        })
+}
+
+/// Returns the prominence of the local maximum at `k`, following the usual
+/// topographic definition.
+fn prominence(db: &[f32], k: usize) -> f32 {
+    let peak = db[k];
+    let base = |range: &mut dyn Iterator<Item = usize>| {
+        let mut lowest = peak;
+        for i in range {
+            if db[i] > peak {
+                break;
+            }
+            lowest = lowest.min(db[i]);
+        }
+        lowest
+    };
+    let left = base(&mut (0..k).rev());
+    let right = base(&mut (k + 1..db.len()));
+    peak - left.max(right)
+}
+
+/// Computes the half-power damping of the peak at bin `k` of the dB spectrum `db`,
+/// searching for the -3 dB points within the bins of `band`. See `Damping` for the
+/// windows it is valid for.
+pub fn half_power_damping(
+    db: &[f32],
+    k: usize,
+    band: Range<usize>,
+    frequency: f32,
+    freq_of_resolution: f32,
+) -> Damping {
+    let (lower, upper, in_band) = half_power_points(db, k, band);
+    let lower = lower * freq_of_resolution;
+    let upper = upper * freq_of_resolution;
+    let bandwidth = upper - lower;
+    let q = if bandwidth > 0.0 {
+        frequency / bandwidth
+    } else {
+        f32::INFINITY
+    };
+    Damping {
+        lower,
+        upper,
+        bandwidth,
+        q,
+        ratio: 1.0 / (2.0 * q),
+        in_band,
+    }
+}
+
+/// Returns the fractional bin positions where the spectrum falls 3 dB below the
+/// peak at `k` on either side, interpolating linearly in dB between bins, and
+/// whether both were found inside `band`.
+fn half_power_points(db: &[f32], k: usize, band: Range<usize>) -> (f32, f32, bool) {
+    let first = band.start.min(k);
+    let last = band.end.min(db.len()).max(k + 1) - 1;
+    let target = db[k] - 10.0 * 2.0_f32.log10();
+    let crossing = |from: usize, to: usize| {
+        let (a, b) = (db[from], db[to]);
+        from as f32 + (to as f32 - from as f32) * (a - target) / (a - b)
+    };
+    let low = (first + 1..=k).rev().find(|&i| db[i - 1] <= target);
+    let high = (k..last).find(|&i| db[i + 1] <= target);
+    (
+        low.map_or(first as f32, |i| crossing(i, i - 1)),
+        high.map_or(last as f32, |i| crossing(i, i + 1)),
+        low.is_some() && high.is_some(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn generate_tones(tones: &[(f32, f32)], sample_rate: f32, duration: f32) -> Vec<f32> {
+        let sample_count = (sample_rate * duration) as usize;
+        (0..sample_count)
+            .map(|i| {
+                tones
+                    .iter()
+                    .map(|(f, a)| a * (2.0 * PI * f * i as f32 / sample_rate).sin())
+                    .sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_peaks_ranks_modes() {
+        // Three modes ringing down from 0.4 s on, with Q of 50, 125 and 250.
+        let sample_rate = 44100.0;
+        let modes = [
+            (440.0, 0.01, 0.05),
+            (1250.5, 0.004, 0.5),
+            (3000.2, 0.002, 0.25),
+        ];
+        let start = 0.4;
+        let samples: Vec<f32> = (0..sample_rate as usize)
+            .map(|i| {
+                let t = i as f32 / sample_rate - start;
+                if t < 0.0 {
+                    return 0.0;
+                }
+                modes
+                    .iter()
+                    .map(|&(f, ratio, a)| {
+                        let omega = 2.0 * PI * f;
+                        a * (-ratio * omega * t).exp() * (omega * t).sin()
+                    })
+                    .sum::<f32>()
+            })
+            .collect();
+        let peaks = find_peaks(
+            &samples,
+            sample_rate,
+            &Estimator::default(),
+            &PeakSettings {
+                max_peaks: 3,
+                ..Default::default()
+            },
+        );
+        assert_eq!(peaks.len(), 3);
+        for (peak, (frequency, ratio, _)) in peaks.iter().zip([modes[1], modes[2], modes[0]]) {
+            // Within a tenth of the half-power bandwidth.
+            assert!(
+                (peak.frequency - frequency).abs() < 0.2 * frequency * ratio,
+                "Expected frequency: {}, but got: {}",
+                frequency,
+                peak.frequency
+            );
+            assert!(peak.prominence > 20.0);
+            assert!(!peak.is_below_noise(&Detection::default()));
+            assert!(peak.damping.in_band);
+            let q = 1.0 / (2.0 * ratio);
+            assert!(
+                (peak.damping.q / q - 1.0).abs() < 0.05,
+                "Expected Q: {}, but got: {}",
+                q,
+                peak.damping.q
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_peaks_spacing_and_threshold() {
+        let sample_rate = 44100.0;
+        let samples = generate_tones(
+            &[(1000.0, 0.5), (1010.0, 0.4), (5000.0, 0.001)],
+            sample_rate,
+            1.0,
+        );
+        let settings = PeakSettings {
+            max_peaks: 10,
+            min_spacing: 50.0,
+            threshold_db: -40.0,
+            ..Default::default()
+        };
+        let estimator = Estimator {
+            window: Window::BlackmanHarris,
+            ..Default::default()
+        };
+        let peaks = find_peaks(&samples, sample_rate, &estimator, &settings);
+        assert_eq!(peaks.len(), 1);
+        assert!((peaks[0].frequency - 1000.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_q_factor_of_damped_oscillator() {
+        let sample_rate = 44100.0;
+        let (frequency, ratio) = (1000.0, 0.01);
+        let omega = 2.0 * PI * frequency;
+        let damped_omega = omega * (1.0_f32 - ratio * ratio).sqrt();
+        let samples: Vec<f32> = (0..sample_rate as usize)
+            .map(|i| {
+                let t = i as f32 / sample_rate;
+                (-ratio * omega * t).exp() * (damped_omega * t).sin()
+            })
+            .collect();
+        let estimator = Estimator {
+            window: Window::Rectangular,
+            ..Default::default()
+        };
+        let peaks = find_peaks(
+            &samples,
+            sample_rate,
+            &estimator,
+            &PeakSettings {
+                max_peaks: 1,
+                ..Default::default()
+            },
+        );
+        let damping = peaks[0].damping;
+        assert!(damping.in_band);
+        assert!(
+            (damping.q - 50.0).abs() < 2.5,
+            "Expected Q: 50, but got: {}",
+            damping.q
+        );
+        assert!((damping.ratio - ratio).abs() < 0.0005);
+    }
+
+    #[test]
+    fn test_check_coherence_flags_and_rejects() {
+        let frf = Frf {
+            frequencies: vec![0.0, 100.0, 200.0, 300.0],
+            response: vec![Default::default(); 4],
+            coherence: vec![1.0, 0.9, 0.3, 0.3],
+        };
+        let peaks = vec![
+            Peak {
+                frequency: 120.0,
+                ..Default::default()
+            },
+            Peak {
+                frequency: 250.0,
+                ..Default::default()
+            },
+        ];
+        let mut settings = PeakSettings::default();
+        let flagged = check_coherence(peaks.clone(), &frf, &settings);
+        assert_eq!(flagged.len(), 2);
+        assert!((flagged[0].coherence.unwrap() - 0.78).abs() < 1e-5);
+        assert!(flagged[0].is_incoherent(&settings));
+        assert!(flagged[1].is_incoherent(&settings));
+
+        settings.min_coherence = 0.5;
+        settings.reject_incoherent = true;
+        let kept = check_coherence(peaks, &frf, &settings);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].frequency, 120.0);
+    }
+
+    #[test]
+    fn test_at_frequency_picks_the_spanning_peak() {
+        let peak = |frequency: f32, bandwidth: f32| Peak {
+            frequency,
+            damping: Damping {
+                lower: frequency - bandwidth / 2.0,
+                upper: frequency + bandwidth / 2.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // The strongest peak comes first, but the resonance is the second one.
+        let peaks = vec![peak(100.0, 10.0), peak(300.0, 20.0), peak(305.0, 40.0)];
+        assert_eq!(at_frequency(&peaks, 302.0).unwrap().frequency, 300.0);
+        assert_eq!(at_frequency(&peaks, 320.0).unwrap().frequency, 305.0);
+        assert!(at_frequency(&peaks, 200.0).is_none());
+    }
+
+    #[test]
+    fn test_half_power_points_outside_band() {
+        let db = [-1.0, 0.0, -1.0, -2.0, -5.0, -9.0];
+        let (lower, upper, in_band) = half_power_points(&db, 1, 0..db.len());
+        assert!(!in_band);
+        assert_eq!(lower, 0.0);
+        assert!((upper - 3.33).abs() < 0.01);
+
+        let (_, _, in_band) = half_power_points(&db, 1, 0..4);
+        assert!(!in_band);
+    }
+}
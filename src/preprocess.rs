@@ -0,0 +1,277 @@
+use std::fmt;
+
+// GUI
+use eframe::egui;
+
+use crate::filter::Biquad;
+
+/// FilterStage is one of the biquad filters of the preprocessing chain.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct FilterStage {
+    pub enabled: bool,
+    /// Cutoff, center or notch frequency in Hz.
+    pub frequency: f32,
+    pub q: f32,
+}
+
+impl FilterStage {
+    fn disabled(frequency: f32, q: f32) -> Self {
+        Self {
+            enabled: false,
+            frequency,
+            q,
+        }
+    }
+}
+
+/// Preprocess is the conditioning chain applied to a capture before it is analyzed.
+///
+/// The stages run in the order of the fields, and a disabled stage is bypassed. It is
+/// saved with the app state, so that a measurement can be repeated after a restart.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Preprocess {
+    pub remove_dc: bool,
+    /// Remove the least squares line through the samples.
+    pub detrend: bool,
+    pub high_pass: FilterStage,
+    pub low_pass: FilterStage,
+    pub band_pass: FilterStage,
+    /// Notch repeated at every harmonic of its frequency up to `notch_harmonics`,
+    /// for mains hum.
+    pub notch: FilterStage,
+    pub notch_harmonics: usize,
+}
+
+impl Default for Preprocess {
+    fn default() -> Self {
+        Self {
+            remove_dc: true,
+            detrend: false,
+            high_pass: FilterStage::disabled(20.0, std::f32::consts::FRAC_1_SQRT_2),
+            low_pass: FilterStage::disabled(20000.0, std::f32::consts::FRAC_1_SQRT_2),
+            band_pass: FilterStage::disabled(1000.0, 1.0),
+            notch: FilterStage::disabled(50.0, 30.0),
+            notch_harmonics: 1,
+        }
+    }
+}
+
+impl fmt::Display for Preprocess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut stages = Vec::new();
+        if self.remove_dc {
+            stages.push("DC removal".to_string());
+        }
+        if self.detrend {
+            stages.push("detrend".to_string());
+        }
+        for (name, stage) in [
+            ("high-pass", self.high_pass),
+            ("low-pass", self.low_pass),
+            ("band-pass", self.band_pass),
+        ] {
+            if stage.enabled {
+                stages.push(format!("{} {} Hz Q {}", name, stage.frequency, stage.q));
+            }
+        }
+        if self.notch.enabled {
+            stages.push(format!(
+                "notch {} Hz Q {} x{}",
+                self.notch.frequency, self.notch.q, self.notch_harmonics
+            ));
+        }
+        if stages.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", stages.join(", "))
+        }
+    }
+}
+
+impl Preprocess {
+    /// Returns `samples` run through the enabled stages. The filters start from rest.
+    pub fn apply(&self, samples: &[f32], sample_rate: f32) -> Vec<f32> {
+        let mut samples = samples.to_vec();
+        if self.remove_dc {
+            remove_dc(&mut samples);
+        }
+        if self.detrend {
+            detrend(&mut samples);
+        }
+        for filter in self.filters(sample_rate) {
+            samples = filter.process(&samples);
+        }
+        samples
+    }
+
+    /// Returns the biquads of the enabled filter stages, leaving out the ones whose
+    /// frequency is not below Nyquist.
+    fn filters(&self, sample_rate: f32) -> Vec<Biquad> {
+        let fits = |frequency: f32| frequency > 0.0 && frequency < sample_rate / 2.0;
+        let mut filters = Vec::new();
+        for (stage, design) in [
+            (
+                self.high_pass,
+                Biquad::high_pass as fn(f32, f32, f32) -> Biquad,
+            ),
+            (self.low_pass, Biquad::low_pass),
+            (self.band_pass, Biquad::band_pass),
+        ] {
+            if stage.enabled && fits(stage.frequency) {
+                filters.push(design(stage.frequency, stage.q, sample_rate));
+            }
+        }
+        if self.notch.enabled {
+            for harmonic in 1..=self.notch_harmonics.max(1) {
+                let frequency = self.notch.frequency * harmonic as f32;
+                if fits(frequency) {
+                    filters.push(Biquad::notch(frequency, self.notch.q, sample_rate));
+                }
+            }
+        }
+        filters
+    }
+
+    pub fn paint_settings(&mut self, ui: &mut egui::Ui, disabled: bool) {
+        ui.horizontal(|ui| {
+            if disabled {
+                ui.disable();
+            }
+            ui.label("Preprocessing:");
+            ui.checkbox(&mut self.remove_dc, "Remove DC");
+            ui.checkbox(&mut self.detrend, "Detrend");
+        });
+        paint_filter_stage(ui, "High-pass", &mut self.high_pass, disabled);
+        paint_filter_stage(ui, "Low-pass", &mut self.low_pass, disabled);
+        paint_filter_stage(ui, "Band-pass", &mut self.band_pass, disabled);
+        ui.horizontal(|ui| {
+            paint_filter_stage(ui, "Notch", &mut self.notch, disabled);
+            if disabled {
+                ui.disable();
+            }
+            ui.label("Harmonics:");
+            ui.add_enabled(
+                self.notch.enabled,
+                egui::DragValue::new(&mut self.notch_harmonics).range(1..=20),
+            );
+        });
+    }
+}
+
+fn paint_filter_stage(ui: &mut egui::Ui, name: &str, stage: &mut FilterStage, disabled: bool) {
+    ui.horizontal(|ui| {
+        if disabled {
+            ui.disable();
+        }
+        ui.checkbox(&mut stage.enabled, name);
+        ui.add_enabled(
+            stage.enabled,
+            egui::DragValue::new(&mut stage.frequency)
+                .speed(1.0)
+                .range(1.0..=96000.0)
+                .suffix(" Hz"),
+        );
+        ui.label("Q:");
+        ui.add_enabled(
+            stage.enabled,
+            egui::DragValue::new(&mut stage.q)
+                .speed(0.05)
+                .range(0.1..=100.0),
+        );
+    });
+}
+
+/// Subtracts the mean of `samples`.
+fn remove_dc(samples: &mut [f32]) {
+    if samples.is_empty() {
+        return;
+    }
+    let mean = samples.iter().map(|&s| s as f64).sum::<f64>() / samples.len() as f64;
+    for s in samples.iter_mut() {
+        *s = (*s as f64 - mean) as f32;
+    }
+}
+
+/// Subtracts the least squares line through `samples`, indexed by sample.
+fn detrend(samples: &mut [f32]) {
+    let n = samples.len() as f64;
+    if n < 2.0 {
+        return;
+    }
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = samples.iter().map(|&s| s as f64).sum::<f64>() / n;
+    let sxy: f64 = samples
+        .iter()
+        .enumerate()
+        .map(|(i, &s)| (i as f64 - mean_x) * (s as f64 - mean_y))
+        .sum();
+    // Sum of (i - mean_x)^2 over 0..n.
+    let sxx = n * (n * n - 1.0) / 12.0;
+    let slope = sxy / sxx;
+    for (i, s) in samples.iter_mut().enumerate() {
+        *s = (*s as f64 - mean_y - slope * (i as f64 - mean_x)) as f32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn level(samples: &[f32], frequency: f32, sample_rate: f32) -> f32 {
+        // Correlate with the tone over the second half, after the transients of the
+        // narrow notches.
+        let half = samples.len() / 2;
+        let (re, im) = samples[half..]
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (i, s)| {
+                let phase = 2.0 * PI * frequency * (half + i) as f32 / sample_rate;
+                (re + s * phase.cos(), im + s * phase.sin())
+            });
+        2.0 * (re * re + im * im).sqrt() / (samples.len() - half) as f32
+    }
+
+    #[test]
+    fn test_preprocess_removes_offset_drift_and_hum() {
+        let sample_rate = 48000.0;
+        let samples: Vec<f32> = (0..2 * sample_rate as usize)
+            .map(|i| {
+                let t = i as f32 / sample_rate;
+                0.3 + 0.2 * t
+                    + 0.5 * (2.0 * PI * 1000.0 * t).sin()
+                    + 0.1 * (2.0 * PI * 50.0 * t).sin()
+                    + 0.05 * (2.0 * PI * 150.0 * t).sin()
+            })
+            .collect();
+
+        let bypassed = Preprocess {
+            remove_dc: false,
+            ..Default::default()
+        };
+        assert_eq!(bypassed.to_string(), "none");
+        assert_eq!(bypassed.apply(&samples, sample_rate), samples);
+
+        let preprocess = Preprocess {
+            detrend: true,
+            notch: FilterStage {
+                enabled: true,
+                ..Preprocess::default().notch
+            },
+            notch_harmonics: 3,
+            ..Default::default()
+        };
+        let output = preprocess.apply(&samples, sample_rate);
+        let mean = output.iter().sum::<f32>() / output.len() as f32;
+        assert!(mean.abs() < 0.01, "Expected no offset, but got: {}", mean);
+        assert!(level(&output, 50.0, sample_rate) < 0.001);
+        assert!(level(&output, 150.0, sample_rate) < 0.001);
+        let tone = level(&output, 1000.0, sample_rate);
+        assert!(
+            (tone - 0.5).abs() < 0.005,
+            "Expected amplitude: 0.5, but got: {}",
+            tone
+        );
+    }
+}
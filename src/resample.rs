@@ -0,0 +1,123 @@
+use crate::window::bessel_i0;
+
+/// Half-length of the interpolation kernel, in samples at the lower of the two
+/// rates.
+const HALF_WIDTH: usize = 64;
+
+/// Kernel values tabulated per sample, linearly interpolated in between.
+const PHASES: usize = 256;
+
+/// Cutoff of the anti-aliasing filter, as a fraction of the lower of the two rates.
+/// The transition band of the kernel is centred on it and ends just below Nyquist.
+const CUTOFF: f64 = 0.47;
+
+/// Beta of the Kaiser window of the kernel, for about 90 dB of stopband rejection.
+const BETA: f32 = 9.0;
+
+/// Returns the number of samples `len` samples at `from` Hz take at `to` Hz.
+pub fn resampled_len(len: usize, from: f32, to: f32) -> usize {
+    if from == to || from <= 0.0 {
+        return len;
+    }
+    (len as f64 * to as f64 / from as f64).round() as usize
+}
+
+/// Returns `samples` taken at `from` Hz resampled to `to` Hz, or a copy of them when
+/// both rates are the same.
+///
+/// Each output sample is interpolated with a Kaiser-windowed sinc, whose cutoff
+/// follows the lower of the two rates so that downsampling does not alias. The
+/// signal is taken as zero outside `samples`, so the first and last few
+/// milliseconds ring if it does not start and stop at rest.
+pub fn resample(samples: &[f32], from: f32, to: f32) -> Vec<f32> {
+    if from == to || from <= 0.0 || to <= 0.0 {
+        return samples.to_vec();
+    }
+    let step = from as f64 / to as f64;
+    // Kernel time is stretched when downsampling, to lower its cutoff.
+    let scale = step.recip().min(1.0);
+    let reach = HALF_WIDTH as f64 / scale;
+    let table = kernel();
+    let kernel = |t: f64| {
+        let x = (t.abs() * scale * PHASES as f64).min((table.len() - 1) as f64);
+        let i = (x as usize).min(table.len() - 2);
+        let fraction = x - i as f64;
+        table[i] + (table[i + 1] - table[i]) * fraction
+    };
+
+    (0..resampled_len(samples.len(), from, to))
+        .map(|n| {
+            let position = n as f64 * step;
+            let first = (position - reach).ceil().max(0.0) as usize;
+            let last = ((position + reach).floor() as usize).min(samples.len() - 1);
+            let sum: f64 = (first..=last)
+                .map(|k| samples[k] as f64 * kernel(position - k as f64))
+                .sum();
+            (sum * scale) as f32
+        })
+        .collect()
+}
+
+/// Returns the right half of the windowed sinc, from its centre to `HALF_WIDTH`
+/// samples away, with `PHASES` values per sample.
+fn kernel() -> Vec<f64> {
+    let len = HALF_WIDTH * PHASES;
+    let window = bessel_i0(BETA) as f64;
+    (0..=len)
+        .map(|i| {
+            let t = i as f64 / PHASES as f64;
+            let r = i as f64 / len as f64;
+            let x = std::f64::consts::PI * 2.0 * CUTOFF * t;
+            let sinc = if i == 0 { 1.0 } else { x.sin() / x };
+            2.0 * CUTOFF * sinc * bessel_i0(BETA * (1.0 - r * r).sqrt() as f32) as f64 / window
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_resample_keeps_tones_and_rejects_aliases() {
+        // Half a second of 1 kHz at 44.1 kHz, up to 192 kHz and back down.
+        let (from, to) = (44100.0, 192000.0);
+        let tone = |rate: f32, frequency: f64, len: usize| -> Vec<f32> {
+            (0..len)
+                .map(|i| (0.5 * (2.0 * PI * frequency * i as f64 / rate as f64).sin()) as f32)
+                .collect()
+        };
+        let samples = tone(from, 1000.0, 22050);
+        let up = resample(&samples, from, to);
+        assert_eq!(up.len(), 96000);
+        let expected = tone(to, 1000.0, up.len());
+        // Away from the ends, where the signal stops abruptly.
+        for i in 2000..94000 {
+            assert!(
+                (up[i] - expected[i]).abs() < 1e-3,
+                "Expected sample {}: {}, but got: {}",
+                i,
+                expected[i],
+                up[i]
+            );
+        }
+        let down = resample(&up, to, from);
+        assert_eq!(down.len(), samples.len());
+        for i in 500..21500 {
+            assert!((down[i] - samples[i]).abs() < 1e-3);
+        }
+
+        // 30 kHz is above the Nyquist frequency of 44.1 kHz, and is filtered out
+        // instead of folding back to 14.1 kHz.
+        let high = resample(&tone(to, 30000.0, 96000), to, from);
+        let level = high[500..21500].iter().fold(0.0_f32, |m, s| m.max(s.abs()));
+        assert!(
+            level < 1e-3,
+            "Expected an alias below 1e-3, but got: {}",
+            level
+        );
+
+        assert_eq!(resample(&samples, from, from), samples);
+    }
+}
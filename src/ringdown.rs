@@ -0,0 +1,196 @@
+use std::f32::consts::PI;
+
+use crate::filter::Biquad;
+
+/// RingdownSettings controls the ring-down analysis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RingdownSettings {
+    /// Quality factor of the band-pass filter isolating the resonance. It has to be
+    /// well below the Q being measured, or the filter decay dominates.
+    pub filter_q: f32,
+    /// Minimum drop in level, in dB, for the end of the excitation to be detected.
+    pub min_drop_db: f32,
+    /// The fit stops this many dB above the noise floor.
+    pub noise_margin_db: f32,
+}
+
+impl Default for RingdownSettings {
+    fn default() -> Self {
+        Self {
+            filter_q: 5.0,
+            min_drop_db: 6.0,
+            noise_margin_db: 10.0,
+        }
+    }
+}
+
+/// Ringdown is an exponential decay fitted to the free response after the
+/// excitation stopped.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ringdown {
+    /// Time in seconds at which the excitation ends.
+    pub excitation_end: f32,
+    /// Time constant of the amplitude decay in seconds.
+    pub time_constant: f32,
+    /// Logarithmic decrement, the log ratio of two successive peaks.
+    pub log_decrement: f32,
+    pub damping_ratio: f32,
+    /// Envelope of the filtered ring-down, as time in seconds and level in dB.
+    pub envelope: Vec<[f32; 2]>,
+    /// End points of the fitted decay line, as time in seconds and level in dB.
+    pub fit: [[f32; 2]; 2],
+}
+
+/// Fits an exponential decay to the ring-down of the resonance at `frequency` that
+/// follows the end of the excitation in `samples`. Returns None if no end of
+/// excitation or no usable decay is found.
+pub fn analyze(
+    samples: &[f32],
+    sample_rate: f32,
+    frequency: f32,
+    settings: &RingdownSettings,
+) -> Option<Ringdown> {
+    if frequency <= 0.0 || frequency >= sample_rate / 2.0 {
+        return None;
+    }
+    // The excitation end is looked for outside the resonance band, where the
+    // response stops with the excitation instead of ringing on.
+    let filtered = Biquad::band_pass(frequency, settings.filter_q, sample_rate).process(samples);
+    let residual: Vec<f32> = samples.iter().zip(&filtered).map(|(s, f)| s - f).collect();
+    let end = excitation_end(&residual, sample_rate, settings.min_drop_db)?;
+
+    // Envelope as the peak of every period of the filtered response, starting once
+    // the filter's own transient has died out.
+    let period = ((sample_rate / frequency).ceil() as usize).max(1);
+    let settle = (3.0 * settings.filter_q / (PI * frequency) * sample_rate) as usize;
+    let envelope: Vec<[f32; 2]> = filtered[(end + settle).min(filtered.len())..]
+        .chunks_exact(period)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let peak = chunk.iter().fold(0.0_f32, |m, s| m.max(s.abs()));
+            let time = (end + settle + i * period + period / 2) as f32 / sample_rate;
+            [time, 20.0 * peak.max(f32::MIN_POSITIVE).log10()]
+        })
+        .collect();
+    if envelope.len() < 4 {
+        return None;
+    }
+
+    // The noise floor is the median level of the last tenth of the envelope.
+    let mut tail: Vec<f32> = envelope[envelope.len() * 9 / 10..]
+        .iter()
+        .map(|p| p[1])
+        .collect();
+    tail.sort_by(f32::total_cmp);
+    let floor = tail[tail.len() / 2] + settings.noise_margin_db;
+    let fit_len = envelope
+        .iter()
+        .position(|p| p[1] < floor)
+        .unwrap_or(envelope.len());
+    if fit_len < 3 {
+        return None;
+    }
+    let (slope, intercept) = linear_fit(&envelope[..fit_len]);
+    if slope >= 0.0 {
+        return None;
+    }
+
+    let decay_rate = -slope * 10.0_f32.ln() / 20.0;
+    let log_decrement = decay_rate / frequency;
+    let (start, stop) = (envelope[0][0], envelope[fit_len - 1][0]);
+    Some(Ringdown {
+        excitation_end: end as f32 / sample_rate,
+        time_constant: 1.0 / decay_rate,
+        log_decrement,
+        damping_ratio: log_decrement / (4.0 * PI * PI + log_decrement * log_decrement).sqrt(),
+        envelope,
+        fit: [
+            [start, intercept + slope * start],
+            [stop, intercept + slope * stop],
+        ],
+    })
+}
+
+/// Returns the sample index where the level of `samples` falls the most, comparing
+/// the mean level of the blocks on either side, if that fall exceeds `min_drop_db`.
+fn excitation_end(samples: &[f32], sample_rate: f32, min_drop_db: f32) -> Option<usize> {
+    const SPAN: usize = 4;
+    let block = ((sample_rate * 0.005) as usize).max(1);
+    let levels: Vec<f32> = samples
+        .chunks_exact(block)
+        .map(|chunk| {
+            let power = chunk.iter().map(|s| s * s).sum::<f32>() / block as f32;
+            10.0 * power.max(f32::MIN_POSITIVE).log10()
+        })
+        .collect();
+    if levels.len() < 2 * SPAN {
+        return None;
+    }
+    let mean = |range: &[f32]| range.iter().sum::<f32>() / range.len() as f32;
+    let (i, drop) = (SPAN..=levels.len() - SPAN)
+        .map(|i| (i, mean(&levels[i - SPAN..i]) - mean(&levels[i..i + SPAN])))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    (drop >= min_drop_db).then_some(i * block)
+}
+
+/// Least squares fit of a line through the points, returned as slope and intercept.
+fn linear_fit(points: &[[f32; 2]]) -> (f32, f32) {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p[0] as f64).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p[1] as f64).sum::<f64>() / n;
+    let (mut sxy, mut sxx) = (0.0, 0.0);
+    for p in points {
+        let dx = p[0] as f64 - mean_x;
+        sxy += dx * (p[1] as f64 - mean_y);
+        sxx += dx * dx;
+    }
+    let slope = if sxx > 0.0 { sxy / sxx } else { 0.0 };
+    (slope as f32, (mean_y - slope * mean_x) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::noise;
+
+    #[test]
+    fn test_ringdown_of_damped_oscillator() {
+        let sample_rate = 48000.0;
+        let (frequency, ratio) = (800.0, 0.004);
+        let omega = 2.0 * PI * frequency;
+        let decay_rate = ratio * omega;
+        let end = sample_rate as usize / 2;
+        // Forced response plus broadband noise until `end`, then the free decay on
+        // top of a low noise floor.
+        let samples: Vec<f32> = noise(1, sample_rate as usize * 2)
+            .into_iter()
+            .enumerate()
+            .map(|(i, noise)| {
+                let t = i as f32 / sample_rate;
+                if i < end {
+                    (omega * t).sin() + 0.5 * noise
+                } else {
+                    let free = t - end as f32 / sample_rate;
+                    (-decay_rate * free).exp() * (omega * t).sin() + 1e-4 * noise
+                }
+            })
+            .collect();
+
+        let ringdown = analyze(&samples, sample_rate, frequency, &Default::default()).unwrap();
+        assert!((ringdown.excitation_end - 0.5).abs() < 0.01);
+        assert!(
+            (ringdown.time_constant - 1.0 / decay_rate).abs() < 0.05 / decay_rate,
+            "Expected time constant: {}, but got: {}",
+            1.0 / decay_rate,
+            ringdown.time_constant
+        );
+        assert!((ringdown.damping_ratio - ratio).abs() < 0.0002);
+        assert!((ringdown.log_decrement - 2.0 * PI * ratio).abs() < 0.002);
+    }
+
+    #[test]
+    fn test_no_ringdown_without_excitation_end() {
+        let samples = vec![0.5; 48000];
+        assert!(analyze(&samples, 48000.0, 1000.0, &Default::default()).is_none());
+    }
+}
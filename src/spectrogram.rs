@@ -0,0 +1,201 @@
+// GUI
+use eframe::egui;
+use egui_plot::{Plot, PlotImage, PlotPoint};
+
+use crate::freq::{self, Spectrogram, Stft, StftSettings};
+use crate::window::Window;
+
+/// Maximum number of frequency rows drawn, neighbouring bins are merged above it.
+const MAX_ROWS: usize = 512;
+
+/// SpectrogramView renders a live spectrogram of a growing capture buffer.
+pub struct SpectrogramView {
+    id: &'static str,
+    settings: StftSettings,
+    stft: Stft,
+    sample_rate: f32,
+    spectrogram: Spectrogram,
+    texture: Option<egui::TextureHandle>,
+    dirty: bool,
+    /// Levels mapped to the bottom and top of the color scale, in dBFS.
+    floor_db: f32,
+    ceiling_db: f32,
+    /// Number of frames kept, older ones scroll out of view.
+    max_frames: usize,
+}
+
+impl SpectrogramView {
+    pub fn new(id: &'static str) -> Self {
+        let settings = StftSettings::default();
+        Self {
+            id,
+            settings,
+            stft: Stft::new(settings),
+            sample_rate: 0.0,
+            spectrogram: Spectrogram::default(),
+            texture: None,
+            dirty: true,
+            floor_db: -120.0,
+            ceiling_db: 0.0,
+            max_frames: 1024,
+        }
+    }
+
+    /// Analyzes the samples added to `buffer` since the last call. The analysis
+    /// starts over when the buffer was cleared or the settings changed.
+    pub fn update(&mut self, buffer: &[f32], sample_rate: f32) {
+        if sample_rate <= 0.0 {
+            return;
+        }
+        if self.stft.settings() != self.settings
+            || self.sample_rate != sample_rate
+            || buffer.len() < self.spectrogram.analyzed_len()
+        {
+            self.stft = Stft::new(self.settings);
+            self.sample_rate = sample_rate;
+            self.spectrogram = freq::stft(buffer, sample_rate, &self.settings);
+            self.dirty = true;
+        } else {
+            let analyzed_len = self.spectrogram.analyzed_len();
+            self.spectrogram.update(&self.stft, buffer, sample_rate);
+            self.dirty |= analyzed_len != self.spectrogram.analyzed_len();
+        }
+        self.spectrogram.keep_last(self.max_frames);
+    }
+
+    pub fn paint_settings(&mut self, ui: &mut egui::Ui, disabled: bool) {
+        ui.horizontal(|ui| {
+            if disabled {
+                ui.disable();
+            }
+            ui.label("Window:");
+            egui::ComboBox::new(format!("{}_window", self.id), "")
+                .selected_text(self.settings.window.to_string())
+                .show_ui(ui, |ui| {
+                    for window in [
+                        Window::Rectangular,
+                        Window::Hann,
+                        Window::Hamming,
+                        Window::BlackmanHarris,
+                        Window::FlatTop,
+                    ] {
+                        ui.selectable_value(&mut self.settings.window, window, window.to_string());
+                    }
+                });
+            ui.label("Frame:");
+            egui::ComboBox::new(format!("{}_frame_len", self.id), "")
+                .selected_text(self.settings.frame_len.to_string())
+                .show_ui(ui, |ui| {
+                    for len in [256, 512, 1024, 2048, 4096, 8192, 16384] {
+                        ui.selectable_value(&mut self.settings.frame_len, len, len.to_string());
+                    }
+                });
+            ui.label("FFT size:");
+            egui::ComboBox::new(format!("{}_fft_len", self.id), "")
+                .selected_text(self.settings.fft_len.to_string())
+                .show_ui(ui, |ui| {
+                    for len in [256, 512, 1024, 2048, 4096, 8192, 16384, 32768] {
+                        ui.selectable_value(&mut self.settings.fft_len, len, len.to_string());
+                    }
+                });
+            self.settings.fft_len = self.settings.fft_len.max(self.settings.frame_len);
+            ui.label("Hop:");
+            ui.add(
+                egui::DragValue::new(&mut self.settings.hop)
+                    .range(16..=self.settings.frame_len)
+                    .suffix(" samples"),
+            );
+            ui.label("Range:");
+            let floor = self.floor_db;
+            ui.add(
+                egui::DragValue::new(&mut self.floor_db)
+                    .range(-200.0..=self.ceiling_db - 10.0)
+                    .suffix(" dBFS"),
+            );
+            self.dirty |= floor != self.floor_db;
+        });
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        let spectrogram = &self.spectrogram;
+        let (Some(first), Some(last)) = (spectrogram.times.first(), spectrogram.times.last())
+        else {
+            ui.label("Not enough samples for a spectrogram yet");
+            return;
+        };
+        let hop = self.settings.hop as f32 / self.sample_rate;
+        let duration = last - first + hop;
+        let nyquist = self.sample_rate / 2.0;
+
+        if self.dirty || self.texture.is_none() {
+            let image = self.image();
+            match &mut self.texture {
+                Some(texture) => texture.set(image, egui::TextureOptions::LINEAR),
+                None => {
+                    self.texture = Some(ui.ctx().load_texture(
+                        self.id,
+                        image,
+                        egui::TextureOptions::LINEAR,
+                    ))
+                }
+            }
+            self.dirty = false;
+        }
+        let Some(texture) = &self.texture else {
+            return;
+        };
+        let image = PlotImage::new(
+            texture,
+            PlotPoint::new(
+                (first - hop / 2.0 + duration / 2.0) as f64,
+                (nyquist / 2.0) as f64,
+            ),
+            [duration, nyquist],
+        );
+        Plot::new(format!("{}_plot", self.id))
+            .height(240.0)
+            .allow_scroll(false)
+            .x_axis_label("Time (s)")
+            .y_axis_label("Frequency (Hz)")
+            .show(ui, |plot_ui| {
+                plot_ui.image(image);
+            });
+    }
+
+    /// Renders the frames as an image, one column per frame and the highest
+    /// frequency on the top row.
+    fn image(&self) -> egui::ColorImage {
+        let frames = &self.spectrogram.frames;
+        let bins = frames.first().map_or(0, |f| f.len());
+        let merge = bins.div_ceil(MAX_ROWS).max(1);
+        let rows = bins.div_ceil(merge);
+        let mut pixels = vec![egui::Color32::BLACK; frames.len() * rows];
+        for (x, frame) in frames.iter().enumerate() {
+            for (row, chunk) in frame.chunks(merge).enumerate() {
+                let level = chunk.iter().cloned().fold(f32::MIN, f32::max);
+                let t = (level - self.floor_db) / (self.ceiling_db - self.floor_db);
+                pixels[(rows - 1 - row) * frames.len() + x] = colormap(t);
+            }
+        }
+        egui::ColorImage {
+            size: [frames.len(), rows],
+            pixels,
+        }
+    }
+}
+
+/// Maps `t` in [0, 1] to a dark-to-bright color scale.
+fn colormap(t: f32) -> egui::Color32 {
+    const STOPS: [[f32; 3]; 5] = [
+        [0.0, 0.0, 4.0],
+        [81.0, 18.0, 124.0],
+        [183.0, 55.0, 121.0],
+        [252.0, 137.0, 97.0],
+        [252.0, 253.0, 191.0],
+    ];
+    let position = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let i = (position as usize).min(STOPS.len() - 2);
+    let f = position - i as f32;
+    let channel = |c: usize| (STOPS[i][c] * (1.0 - f) + STOPS[i + 1][c] * f) as u8;
+    egui::Color32::from_rgb(channel(0), channel(1), channel(2))
+}
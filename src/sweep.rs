@@ -0,0 +1,259 @@
+use std::f32::consts::PI;
+
+use rustfft::num_complex::Complex;
+
+use crate::freq;
+use crate::frf::Frf;
+use crate::window::Window;
+
+/// SweepSettings describes an exponential (logarithmic) sine sweep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepSettings {
+    pub start_freq: f32,
+    pub end_freq: f32,
+    /// Duration of the sweep in seconds.
+    pub duration: f32,
+}
+
+impl Default for SweepSettings {
+    fn default() -> Self {
+        Self {
+            start_freq: 20.0,
+            end_freq: 20000.0,
+            duration: 5.0,
+        }
+    }
+}
+
+impl SweepSettings {
+    /// Returns the sweep rate L in seconds, the time it takes the instantaneous
+    /// frequency to grow by a factor of e.
+    pub fn rate(&self) -> f32 {
+        self.duration / (self.end_freq / self.start_freq).ln()
+    }
+
+    /// Returns the time in seconds by which the impulse response of the harmonic of
+    /// the given order precedes the linear one after deconvolution.
+    pub fn harmonic_delay(&self, order: usize) -> f32 {
+        self.rate() * (order as f32).ln()
+    }
+
+    /// Returns the sweep sampled at `sample_rate`, with short fades at both ends to
+    /// limit the ripple they would otherwise cause.
+    pub fn samples(&self, sample_rate: f32) -> Vec<f32> {
+        let len = (self.duration * sample_rate) as usize;
+        let rate = self.rate();
+        let fade_in = len / 100;
+        let fade_out = len / 200;
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / sample_rate;
+                let phase = 2.0 * PI * self.start_freq * rate * ((t / rate).exp() - 1.0);
+                let gain = if i < fade_in {
+                    0.5 * (1.0 - (PI * i as f32 / fade_in as f32).cos())
+                } else if i >= len - fade_out {
+                    0.5 * (1.0 - (PI * (len - 1 - i) as f32 / fade_out as f32).cos())
+                } else {
+                    1.0
+                };
+                gain * phase.sin()
+            })
+            .collect()
+    }
+
+    /// Returns the inverse filter of the sweep: the time reversed sweep with an
+    /// amplitude envelope falling 6 dB per octave, compensating the pink spectrum of
+    /// the sweep so that their convolution is a band-limited impulse.
+    pub fn inverse_filter(&self, sample_rate: f32) -> Vec<f32> {
+        let rate = self.rate();
+        self.samples(sample_rate)
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, s)| s * (-(i as f32) / sample_rate / rate).exp())
+            .collect()
+    }
+}
+
+/// SweepResponse is the result of deconvolving a captured exponential sweep.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SweepResponse {
+    /// Deconvolved impulse response, with the harmonic impulses preceding the linear
+    /// one.
+    pub impulse_response: Vec<f32>,
+    /// Index of the linear impulse in `impulse_response`.
+    pub linear_index: usize,
+    /// Linear frequency response.
+    pub linear: Frf,
+    /// Frequency responses of the harmonics of order 2, 3 ..., sampled at the
+    /// excitation frequency rather than the harmonic one.
+    pub harmonics: Vec<Frf>,
+}
+
+impl SweepResponse {
+    /// Returns the linear and harmonic magnitudes in dB as a header and rows, one row
+    /// per frequency. Harmonics above the Nyquist frequency are left as NaN.
+    pub fn table(&self) -> (Vec<String>, Vec<Vec<f32>>) {
+        let mut header = vec!["Frequency (Hz)".to_string(), "H1 (dB)".to_string()];
+        header.extend((0..self.harmonics.len()).map(|i| format!("H{} (dB)", i + 2)));
+        let linear = self.linear.magnitude_db();
+        let harmonics: Vec<Vec<f32>> = self.harmonics.iter().map(|h| h.magnitude_db()).collect();
+        let rows = self
+            .linear
+            .frequencies
+            .iter()
+            .enumerate()
+            .map(|(k, f)| {
+                let mut row = vec![*f, linear[k]];
+                row.extend(
+                    harmonics
+                        .iter()
+                        .map(|h| h.get(k).copied().unwrap_or(f32::NAN)),
+                );
+                row
+            })
+            .collect();
+        (header, rows)
+    }
+}
+
+/// Deconvolves `capture`, the response to the sweep described by `settings`, and
+/// separates the linear response from the harmonics up to `max_order`.
+pub fn deconvolve(
+    capture: &[f32],
+    settings: &SweepSettings,
+    sample_rate: f32,
+    max_order: usize,
+) -> SweepResponse {
+    let inverse = settings.inverse_filter(sample_rate);
+    if capture.is_empty() || inverse.is_empty() {
+        return SweepResponse::default();
+    }
+    let gain = passband_gain(
+        &settings.samples(sample_rate),
+        &inverse,
+        settings,
+        sample_rate,
+    );
+    let impulse_response: Vec<f32> = freq::convolve(capture, &inverse)
+        .into_iter()
+        .map(|s| s / gain)
+        .collect();
+    let linear_index = impulse_response
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))
+        .map_or(0, |(i, _)| i);
+
+    // Every impulse gets the same window, as long as the gap between the two
+    // closest ones, those of the two highest orders.
+    let max_order = max_order.max(1);
+    let gap = settings.rate() * ((max_order + 1) as f32 / max_order as f32).ln() * sample_rate;
+    let window_len = ((gap as usize + 1).next_power_of_two() / 2).max(64);
+    let pre = window_len / 16;
+    let taper = Window::Tukey(0.1).coefficients(window_len);
+    let segment = |center: usize| -> Vec<f32> {
+        (0..window_len)
+            .map(|i| {
+                (center + i)
+                    .checked_sub(pre)
+                    .and_then(|j| impulse_response.get(j))
+                    .map_or(0.0, |s| s * taper[i])
+            })
+            .collect()
+    };
+    let frequencies: Vec<f32> = (0..window_len / 2)
+        .map(|k| k as f32 * sample_rate / window_len as f32)
+        .collect();
+
+    let linear = freq::spectrum(&segment(linear_index), Window::Rectangular, 1);
+    let harmonics = (2..=max_order)
+        .map(|order| {
+            let delay = (settings.harmonic_delay(order) * sample_rate).round() as usize;
+            let spectrum = linear_index
+                .checked_sub(delay)
+                .map(|center| freq::spectrum(&segment(center), Window::Rectangular, 1))
+                .unwrap_or_default();
+            let response: Vec<Complex<f32>> = spectrum.iter().step_by(order).copied().collect();
+            Frf {
+                frequencies: frequencies[..response.len()].to_vec(),
+                response,
+                coherence: Vec::new(),
+            }
+        })
+        .collect();
+    SweepResponse {
+        linear: Frf {
+            frequencies,
+            response: linear,
+            coherence: Vec::new(),
+        },
+        harmonics,
+        linear_index,
+        impulse_response,
+    }
+}
+
+/// Returns the magnitude of the sweep convolved with its inverse filter at the
+/// geometric center of the sweep, used to normalize the impulse response.
+fn passband_gain(
+    sweep: &[f32],
+    inverse: &[f32],
+    settings: &SweepSettings,
+    sample_rate: f32,
+) -> f32 {
+    let center = (settings.start_freq * settings.end_freq).sqrt();
+    let omega = 2.0 * std::f64::consts::PI * center as f64 / sample_rate as f64;
+    let (re, im) = freq::convolve(sweep, inverse).iter().enumerate().fold(
+        (0.0_f64, 0.0_f64),
+        |(re, im), (n, s)| {
+            let phase = omega * n as f64;
+            (re + *s as f64 * phase.cos(), im - *s as f64 * phase.sin())
+        },
+    );
+    ((re * re + im * im).sqrt() as f32).max(f32::MIN_POSITIVE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn magnitude_at(frf: &Frf, frequency: f32) -> f32 {
+        let resolution = frf.frequencies[1];
+        frf.magnitude_db()[(frequency / resolution).round() as usize]
+    }
+
+    #[test]
+    fn test_deconvolve_separates_harmonics() {
+        let sample_rate = 48000.0;
+        let settings = SweepSettings {
+            start_freq: 50.0,
+            end_freq: 20000.0,
+            duration: 2.0,
+        };
+        let sweep = settings.samples(sample_rate);
+        // A static nonlinearity: 0.25 sin² = 0.125 - 0.125 cos(2wt), a second harmonic
+        // at -18 dB and no third harmonic.
+        let mut capture: Vec<f32> = sweep.iter().map(|x| 0.5 * x + 0.25 * x * x).collect();
+        capture.resize(capture.len() + sample_rate as usize / 2, 0.0);
+
+        let response = deconvolve(&capture, &settings, sample_rate, 3);
+        assert_eq!(response.harmonics.len(), 2);
+        for frequency in [200.0, 1000.0, 4000.0] {
+            let linear = magnitude_at(&response.linear, frequency);
+            let second = magnitude_at(&response.harmonics[0], frequency);
+            let third = magnitude_at(&response.harmonics[1], frequency);
+            assert!(
+                (linear + 6.02).abs() < 0.5,
+                "Expected H1: -6.02 dB, but got: {}",
+                linear
+            );
+            assert!(
+                (second + 18.06).abs() < 1.5,
+                "Expected H2: -18.06 dB, but got: {}",
+                second
+            );
+            assert!(third < -40.0, "Expected no H3, but got: {}", third);
+        }
+    }
+}
@@ -21,6 +21,6 @@ impl Tasker {
         T: Send + std::future::Future + 'static,
         T::Output: Send + 'static,
     {
-        let _ = self.rt.spawn(t);
+        drop(self.rt.spawn(t));
     }
 }
@@ -0,0 +1,11 @@
+/// Returns `len` samples of deterministic white noise, uniform in [-1, 1], from a
+/// linear congruential generator started at `seed`.
+pub fn noise(seed: u32, len: usize) -> Vec<f32> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 8) as f32 / (1 << 24) as f32 * 2.0 - 1.0
+        })
+        .collect()
+}
@@ -1,2 +1,2 @@
-///
+/// Result is the result type shared by the UI code.
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
@@ -41,14 +41,14 @@ impl Wave {
 
         // Generate the sine wave and store it in the Vec<[f32; 2]>
         for _ in 0..total_samples {
-            let sample_value = (two_pi_f * sample_clock / sample_rate as f32).sin();
+            let sample_value = (two_pi_f * sample_clock / sample_rate).sin();
 
             // Store the same value for both left and right channels (mono output in stereo format)
             sine_wave.push(sample_value);
 
             // Increment the sample clock
             sample_clock += 1.0;
-            if sample_clock > sample_rate as f32 {
+            if sample_clock > sample_rate {
                 sample_clock = 0.0;
             }
         }
@@ -59,7 +59,7 @@ impl Wave {
 impl Iterator for Wave {
     type Item = f32;
     fn next(&mut self) -> Option<f32> {
-        let index = self.index as usize;
+        let index = self.index;
         if index < self.samples.len() {
             let sample = self.samples[index];
             self.index += 1;
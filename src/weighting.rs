@@ -0,0 +1,175 @@
+use std::f64::consts::PI;
+use std::fmt;
+
+use crate::filter::Biquad;
+
+/// Pole frequencies in Hz of the weighting curves of IEC 61672-1.
+const F1: f64 = 20.598997;
+const F2: f64 = 107.65265;
+const F3: f64 = 737.86223;
+const F4: f64 = 12194.217;
+
+/// Weighting is a standard sound level meter frequency weighting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Weighting {
+    A,
+    C,
+    /// Zero weighting, the flat response.
+    #[default]
+    Z,
+}
+
+impl fmt::Display for Weighting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Weighting::A => write!(f, "A"),
+            Weighting::C => write!(f, "C"),
+            Weighting::Z => write!(f, "Z"),
+        }
+    }
+}
+
+impl Weighting {
+    /// Returns the unit of a weighted level, such as dB(A).
+    pub fn unit(&self) -> String {
+        format!("dB({})", self)
+    }
+
+    /// Returns the gain in dB of the weighting at `frequency`, from the analog
+    /// response of IEC 61672-1. It is 0 dB at 1 kHz.
+    pub fn gain_db(&self, frequency: f32) -> f32 {
+        let f2 = (frequency as f64).powi(2);
+        let (gain, offset) = match self {
+            Weighting::A => (
+                F4 * F4 * f2 * f2
+                    / ((f2 + F1 * F1) * ((f2 + F2 * F2) * (f2 + F3 * F3)).sqrt() * (f2 + F4 * F4)),
+                2.0,
+            ),
+            Weighting::C => (F4 * F4 * f2 / ((f2 + F1 * F1) * (f2 + F4 * F4)), 0.062),
+            Weighting::Z => return 0.0,
+        };
+        (20.0 * gain.max(f64::MIN_POSITIVE).log10() + offset) as f32
+    }
+
+    /// Returns the weighting as a cascade of biquads, the bilinear transform of the
+    /// analog poles and zeros scaled to 0 dB at 1 kHz.
+    ///
+    /// The bilinear transform squeezes the response towards Nyquist, so the filters
+    /// only follow the curve to within the class 1 tolerances at high sample rates.
+    pub fn filters(&self, sample_rate: f32) -> Vec<Biquad> {
+        let w = |f: f64| 2.0 * PI * f;
+        let high_pass = Biquad::bilinear(
+            [1.0, 0.0, 0.0],
+            [1.0, 2.0 * w(F1), w(F1).powi(2)],
+            sample_rate,
+        );
+        let low_pass = Biquad::bilinear(
+            [0.0, 0.0, 1.0],
+            [1.0, 2.0 * w(F4), w(F4).powi(2)],
+            sample_rate,
+        );
+        let mut filters = match self {
+            Weighting::A => vec![
+                high_pass,
+                Biquad::bilinear(
+                    [1.0, 0.0, 0.0],
+                    [1.0, w(F2) + w(F3), w(F2) * w(F3)],
+                    sample_rate,
+                ),
+                low_pass,
+            ],
+            Weighting::C => vec![high_pass, low_pass],
+            Weighting::Z => return Vec::new(),
+        };
+        let gain: f64 = filters
+            .iter()
+            .map(|f| f.gain(1000.0, sample_rate))
+            .product();
+        let first = &mut filters[0];
+        first.b0 /= gain;
+        first.b1 /= gain;
+        first.b2 /= gain;
+        filters
+    }
+
+    /// Returns `samples` filtered by the weighting.
+    pub fn apply(&self, samples: &[f32], sample_rate: f32) -> Vec<f32> {
+        let mut samples = samples.to_vec();
+        for filter in self.filters(sample_rate) {
+            samples = filter.process(&samples);
+        }
+        samples
+    }
+
+    /// Returns the weighted mean square level of `samples` in dBFS, 0 dBFS being a
+    /// full scale sine.
+    pub fn level_db(&self, samples: &[f32], sample_rate: f32) -> f32 {
+        if samples.is_empty() {
+            return f32::NEG_INFINITY;
+        }
+        let weighted = self.apply(samples, sample_rate);
+        let mean_square =
+            weighted.iter().map(|&s| s as f64 * s as f64).sum::<f64>() / weighted.len() as f64;
+        10.0 * ((2.0 * mean_square) as f32).max(f32::MIN_POSITIVE).log10()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighting_curves_and_filters() {
+        // Design goals of IEC 61672-1, table 3, at the exact band frequencies.
+        for (frequency, a, c) in [
+            (31.623, -39.4, -3.0),
+            (100.0, -19.1, -0.3),
+            (1000.0, 0.0, 0.0),
+            (3981.1, 1.0, -0.8),
+            (10000.0, -2.5, -4.4),
+        ] {
+            assert!(
+                (Weighting::A.gain_db(frequency) - a).abs() < 0.1,
+                "Expected A weighting: {}, but got: {}",
+                a,
+                Weighting::A.gain_db(frequency)
+            );
+            assert!((Weighting::C.gain_db(frequency) - c).abs() < 0.1);
+            assert_eq!(Weighting::Z.gain_db(frequency), 0.0);
+
+            let sample_rate = 192000.0;
+            for weighting in [Weighting::A, Weighting::C] {
+                let gain: f64 = weighting
+                    .filters(sample_rate)
+                    .iter()
+                    .map(|f| f.gain(frequency, sample_rate))
+                    .product();
+                let gain_db = 20.0 * gain.log10() as f32;
+                assert!(
+                    (gain_db - weighting.gain_db(frequency)).abs() < 0.3,
+                    "Expected {} filter gain: {}, but got: {}",
+                    weighting,
+                    weighting.gain_db(frequency),
+                    gain_db
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_weighted_level() {
+        let sample_rate = 48000.0;
+        let samples: Vec<f32> = (0..sample_rate as usize)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / sample_rate).sin())
+            .collect();
+        for weighting in [Weighting::A, Weighting::C, Weighting::Z] {
+            let level = weighting.level_db(&samples, sample_rate);
+            assert!(
+                (level + 6.02).abs() < 0.1,
+                "Expected {}: -6.02, but got: {}",
+                weighting.unit(),
+                level
+            );
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::f32::consts::PI;
+
+/// Window is a tapering function applied to a block of samples before the FFT.
+///
+/// All windows are generated in their periodic (DFT-even) form, which is the one
+/// suited to spectral analysis.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Window {
+    Rectangular,
+    #[default]
+    Hann,
+    Hamming,
+    /// 4-term Blackman-Harris, -92 dB sidelobes.
+    BlackmanHarris,
+    /// 5-term flat-top, for amplitude accurate readings.
+    FlatTop,
+    /// Kaiser window with the given beta.
+    Kaiser(f32),
+    /// Tukey window with the given tapered fraction alpha in [0, 1].
+    Tukey(f32),
+}
+
+impl std::fmt::Display for Window {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Window::Rectangular => write!(f, "Rectangular"),
+            Window::Hann => write!(f, "Hann"),
+            Window::Hamming => write!(f, "Hamming"),
+            Window::BlackmanHarris => write!(f, "Blackman-Harris"),
+            Window::FlatTop => write!(f, "Flat-top"),
+            Window::Kaiser(_) => write!(f, "Kaiser"),
+            Window::Tukey(_) => write!(f, "Tukey"),
+        }
+    }
+}
+
+impl Window {
+    /// Returns the window coefficients for a block of `len` samples.
+    pub fn coefficients(&self, len: usize) -> Vec<f32> {
+        let n = len as f32;
+        (0..len)
+            .map(|i| {
+                let x = i as f32 / n;
+                match *self {
+                    Window::Rectangular => 1.0,
+                    Window::Hann => cosine_sum(&[0.5, 0.5], x),
+                    Window::Hamming => cosine_sum(&[0.54, 0.46], x),
+                    Window::BlackmanHarris => cosine_sum(&[0.35875, 0.48829, 0.14128, 0.01168], x),
+                    Window::FlatTop => cosine_sum(
+                        &[
+                            0.215_578_95,
+                            0.416_631_58,
+                            0.277_263_16,
+                            0.083_578_95,
+                            0.006_947_368,
+                        ],
+                        x,
+                    ),
+                    Window::Kaiser(beta) => {
+                        let r = 2.0 * x - 1.0;
+                        bessel_i0(beta * (1.0 - r * r).max(0.0).sqrt()) / bessel_i0(beta)
+                    }
+                    Window::Tukey(alpha) => tukey(alpha.clamp(0.0, 1.0), x),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns a windowed copy of `samples`.
+    pub fn apply(&self, samples: &[f32]) -> Vec<f32> {
+        samples
+            .iter()
+            .zip(self.coefficients(samples.len()))
+            .map(|(s, w)| s * w)
+            .collect()
+    }
+
+    /// Returns the factor that restores the amplitude of a tone after windowing,
+    /// i.e. the inverse of the coherent gain.
+    pub fn amplitude_correction(&self, len: usize) -> f32 {
+        let sum: f32 = self.coefficients(len).iter().sum();
+        len as f32 / sum
+    }
+
+    /// Returns the factor that restores the energy (RMS) of a broadband signal
+    /// after windowing.
+    pub fn energy_correction(&self, len: usize) -> f32 {
+        let sum_sq: f32 = self.coefficients(len).iter().map(|w| w * w).sum();
+        (len as f32 / sum_sq).sqrt()
+    }
+
+    /// Returns the equivalent noise bandwidth in bins.
+    pub fn enbw(&self, len: usize) -> f32 {
+        let amplitude = self.amplitude_correction(len);
+        let energy = self.energy_correction(len);
+        (amplitude * amplitude) / (energy * energy)
+    }
+}
+
+/// Evaluates a cosine-sum window with alternating signs at the normalized position x.
+fn cosine_sum(coefficients: &[f32], x: f32) -> f32 {
+    coefficients
+        .iter()
+        .enumerate()
+        .map(|(k, a)| {
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+            sign * a * (2.0 * PI * k as f32 * x).cos()
+        })
+        .sum()
+}
+
+fn tukey(alpha: f32, x: f32) -> f32 {
+    if alpha == 0.0 {
+        return 1.0;
+    }
+    let edge = alpha / 2.0;
+    if x < edge {
+        0.5 * (1.0 - (PI * x / edge).cos())
+    } else if x > 1.0 - edge {
+        0.5 * (1.0 - (PI * (1.0 - x) / edge).cos())
+    } else {
+        1.0
+    }
+}
+
+/// Zeroth order modified Bessel function of the first kind.
+pub fn bessel_i0(x: f32) -> f32 {
+    let half = x as f64 / 2.0;
+    let mut term = 1.0_f64;
+    let mut sum = 1.0_f64;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= (half / k) * (half / k);
+        sum += term;
+        k += 1.0;
+    }
+    sum as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correction_factors() {
+        let len = 4096;
+        assert!((Window::Rectangular.amplitude_correction(len) - 1.0).abs() < 1e-4);
+        assert!((Window::Hann.amplitude_correction(len) - 2.0).abs() < 1e-3);
+        assert!((Window::Hann.energy_correction(len) - (8.0_f32 / 3.0).sqrt()).abs() < 1e-3);
+        assert!((Window::Hann.enbw(len) - 1.5).abs() < 1e-3);
+        assert!((Window::BlackmanHarris.enbw(len) - 2.0044).abs() < 1e-3);
+        assert!((Window::FlatTop.amplitude_correction(len) - 1.0 / 0.215_578_95).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_kaiser_and_tukey_limits() {
+        let len = 256;
+        let rect = Window::Rectangular.coefficients(len);
+        for (a, b) in Window::Kaiser(0.0).coefficients(len).iter().zip(&rect) {
+            assert!((a - b).abs() < 1e-6);
+        }
+        for (a, b) in Window::Tukey(0.0).coefficients(len).iter().zip(&rect) {
+            assert!((a - b).abs() < 1e-6);
+        }
+        for (a, b) in Window::Tukey(1.0)
+            .coefficients(len)
+            .iter()
+            .zip(Window::Hann.coefficients(len))
+        {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+}